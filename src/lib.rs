@@ -1,22 +1,25 @@
 mod cli;
 mod log_info;
-mod notification;
+pub mod notification;
+mod outbox;
 mod state;
 
 use std::{
-    fs::read_dir,
+    collections::HashSet,
+    fs::{self, read_dir},
     path::{Path, PathBuf},
 };
 
 use anyhow::{anyhow, Context};
 pub use cli::Cli;
-pub use log_info::LogInfo;
-use notification::send_notification;
-pub use state::AppState;
+pub use log_info::{ItemStats, LogEntry, LogInfo, Severity, Summary};
+use notification::{send_test_notification, Channel, Message, NotificationConfig};
+pub use outbox::{Delivery, Outbox, QueuedMessage};
+pub use state::{AppState, LogIdentity, ProfileState, SeenLog, SymlinkPolicy, WatchedFolder};
 
 pub fn run(cli: &Cli) -> anyhow::Result<()> {
     let (state_file, config_folder) = get_canonical_folder_and_filename(&cli.state_file)?;
-    let mut app_state = AppState::load(state_file).context("failed to load state")?;
+    let mut app_state = AppState::load(&state_file).context("failed to load state")?;
 
     if cli.print_state_only {
         println!("{app_state:#?}");
@@ -24,107 +27,374 @@ pub fn run(cli: &Cli) -> anyhow::Result<()> {
     }
 
     if let Some(msg) = &cli.test_notification {
-        send_notification(msg, &config_folder).context("sending test notification failed")?;
+        send_test_notification(&Message::new(Severity::Info, msg), &config_folder)
+            .context("sending test notification failed")?;
         println!("TEST NOTIFICATION SENT");
         return Ok(());
     }
 
-    if app_state.alive_msg_due() {
-        let alive_msg = app_state.generate_alive_msg();
-        send_notification(&alive_msg, &config_folder).context("failed to send alive message")?;
+    let notification_config =
+        NotificationConfig::load(&config_folder).context("failed to load notification config")?;
+    let channels = notification_config.build_channels(&config_folder);
+    let outbox_file = Outbox::path_for_state_file(&state_file);
+    let mut outbox = Outbox::load(&outbox_file).context("failed to load outbox")?;
+    let max_age = notification_config.outbox_max_age()?;
+    for expired in outbox.remove_expired(max_age) {
+        eprintln!(
+            "giving up on message queued at {} as it is older than {} hours:\n{}",
+            expired.queued_at.format("%F %T"),
+            notification_config.outbox_max_age_hours,
+            expired.msg.text
+        );
     }
+    outbox.flush(&channels);
 
-    match process_logs_folder(&mut app_state).context("error processing logs") {
-        Ok(log_infos) => {
-            if !log_infos.is_empty() {
-                let err_msg = build_err_msg_from_logs(log_infos);
-                send_notification(&err_msg, &config_folder)
-                    .context("failed to send notification of errors")?
-            }
-        }
-        Err(e) => send_notification(&e.to_string(), &config_folder)
-            .context("failed to send notification of processing failure")?,
+    if app_state.alive_msg_due() {
+        let alive_msg = Message::new(Severity::Info, app_state.generate_alive_msg());
+        outbox.send_or_queue(alive_msg, &channels);
     }
 
-    if let Some(msg) = app_state.generate_inactivity_msg() {
-        send_notification(&msg, &config_folder)
-            .context("failed to send notification of inactivity in logs")?
-    }
+    // The state is still saved if this fails as the logs may have been marked as processed
+    let report_result = report_new_logs(&mut app_state, &mut outbox, &outbox_file, &channels);
 
+    if !outbox.messages().is_empty() {
+        eprintln!(
+            "{} messages waiting in outbox to be retried on the next run",
+            outbox.messages().len()
+        );
+    }
     if app_state.is_changed() {
         app_state
             .save(&cli.state_file)
             .context("failed to save state")?;
     }
+    report_result?;
     println!("RUN COMPLETED");
     Ok(())
 }
 
-pub fn build_err_msg_from_logs(log_infos: Vec<LogInfo>) -> String {
-    const MAX_MSG_LEN: usize = 2000;
-    let log_count = log_infos.len();
-    // Stores the running count of `Errors and warnings` found
-    let mut entry_count = 0;
+/// Processes the watched folders and sends a message about what was found
+///
+/// The logs found are only marked as processed in `app_state` once the message has been sent or
+/// saved to the outbox, so that they are found again on the next run if neither happened.
+/// Returns None if there was nothing to report. The outbox is saved if it changed, failing to
+/// save it is still an error if the message was sent (the logs are marked as processed first).
+pub fn report_new_logs(
+    app_state: &mut AppState,
+    outbox: &mut Outbox,
+    outbox_file: &Path,
+    channels: &[Channel],
+) -> anyhow::Result<Option<Delivery>> {
+    let processed_logs = process_logs_folders(app_state);
+
+    // Inactivity is based on the state as it will be once the logs found are processed
+    let mut pending_state = app_state.clone();
+    pending_state.mark_logs_processed(&processed_logs.seen_logs);
+    let inactivity_msgs = pending_state.generate_inactivity_msgs();
+
+    let delivery = build_combined_msg(processed_logs, inactivity_msgs)
+        .map(|msg| outbox.send_or_queue(msg, channels));
+    if delivery == Some(Delivery::Queued) {
+        // Only durable once the outbox is saved
+        outbox
+            .save(outbox_file)
+            .context("failed to save outbox, new logs not marked as processed")?;
+        *app_state = pending_state;
+    } else {
+        *app_state = pending_state;
+        if outbox.is_changed() {
+            outbox.save(outbox_file).context("failed to save outbox")?;
+        }
+    }
+    Ok(delivery)
+}
+
+/// Combines the errors found in all the folders into one message (None if there is nothing to report)
+///
+/// The severity is the highest of the logs included, failures and inactivity are treated as errors
+/// and other notices as warnings
+fn build_combined_msg(
+    processed_logs: ProcessedLogs,
+    inactivity_msgs: Vec<String>,
+) -> Option<Message> {
+    let ProcessedLogs {
+        log_infos,
+        failures,
+        notices,
+        seen_logs: _,
+    } = processed_logs;
+    let mut severity = log_infos
+        .iter()
+        .map(|x| x.severity())
+        .max()
+        .unwrap_or(Severity::Info);
+    if !failures.is_empty() || !inactivity_msgs.is_empty() {
+        severity = Severity::Error;
+    } else if !notices.is_empty() {
+        severity = severity.max(Severity::Warning);
+    }
+    let notes: Vec<String> = failures
+        .iter()
+        .map(|failure| format!("{failure:#}"))
+        .chain(notices)
+        .chain(inactivity_msgs)
+        .collect();
+    let mut result = String::new();
+    if !log_infos.is_empty() {
+        result.push_str(&build_err_msg_from_logs(&log_infos));
+    }
+    for note in notes.iter() {
+        result.push_str(note);
+        result.push('\n');
+    }
+    (!result.is_empty()).then_some(Message {
+        severity,
+        text: result,
+        log_infos,
+        notes,
+    })
+}
+
+/// The full report for the logs, channels with a limit on message length split or shorten it themselves
+pub fn build_err_msg_from_logs(log_infos: &[LogInfo]) -> String {
     let mut result = String::new();
     let separator = "---\n";
     for log_info in log_infos {
-        entry_count += log_info.errors_and_warnings.len();
         result.push_str(separator);
         result.push_str(&log_info.to_string());
     }
     result.push_str(separator);
-    let summary = format!("{log_count} logs with {entry_count} error and warnings\n");
-    result.push_str(&summary);
+    result.push_str(&logs_summary_line(log_infos));
+    result.push('\n');
     result.push_str(separator);
-    if result.len() > MAX_MSG_LEN {
-        // Exceeded limit for discord, only send summary
-        result = summary;
-        result.push_str(separator);
-        result.push_str("DETAILS TOO LONG TO INCLUDE **TRUNCATED**");
-        result.push_str(separator);
-    }
     result
 }
 
-/// Returns a list of the new logs with errors
-pub fn process_logs_folder(app_state: &mut AppState) -> anyhow::Result<Vec<LogInfo>> {
-    let mut result = Vec::new();
-    let mut latest_timestamp = app_state.latest_log_datetime();
-    for dir_entry in read_dir(app_state.logs_dir())
-        .with_context(|| format!("failed to read log folder: {:?}", app_state.logs_dir()))?
-    {
-        let dir_entry = dir_entry.with_context(|| {
-            format!("failed to read entry in folder: {:?}", app_state.logs_dir())
-        })?;
-
-        if dir_entry
-            .file_type()
-            .with_context(|| format!("failed to get file type for: {:?}", dir_entry.path()))?
-            .is_file()
+/// One line stating how many logs and `Errors and warnings` entries there are in total
+pub fn logs_summary_line(log_infos: &[LogInfo]) -> String {
+    let entry_count: usize = log_infos.iter().map(|x| x.errors_and_warnings.len()).sum();
+    format!(
+        "{} logs with {entry_count} error and warnings",
+        log_infos.len()
+    )
+}
+
+/// The new logs with errors from all the watched folders
+#[derive(Debug, Default)]
+pub struct ProcessedLogs {
+    pub log_infos: Vec<LogInfo>,
+    /// Errors for the folders that could not be processed
+    pub failures: Vec<anyhow::Error>,
+    /// Other information to be reported (eg. unrecognised files present)
+    pub notices: Vec<String>,
+    /// The new logs found in each watched folder (empty for folders that failed), in the same
+    /// order as the watched folders. See [`AppState::mark_logs_processed`]
+    pub seen_logs: Vec<Vec<SeenLog>>,
+}
+
+/// The new logs found in one watched folder
+#[derive(Debug, Default)]
+pub struct FolderLogs {
+    pub log_infos: Vec<LogInfo>,
+    /// Files that matched the folder's patterns but were not recognised as logs and were skipped
+    pub unrecognised_files: Vec<PathBuf>,
+    /// Every new log found, including those not reported
+    pub seen_logs: Vec<SeenLog>,
+    /// Reported logs that are older than logs already processed
+    pub late_logs: Vec<PathBuf>,
+    /// Problems with logs that did not prevent them from being processed (eg. no summary found)
+    pub notes: Vec<String>,
+}
+
+/// Processes each watched folder, a failure in one folder does not prevent the others from being processed
+///
+/// The state is not changed, the logs found need to be marked as processed afterwards
+pub fn process_logs_folders(app_state: &AppState) -> ProcessedLogs {
+    let min_severity = app_state.min_severity();
+    let mut result = ProcessedLogs::default();
+    for folder in app_state.watched_folders() {
+        match process_logs_folder(folder, min_severity)
+            .with_context(|| format!("error processing logs in {:?}", folder.display_name()))
         {
-            let mut log_info = LogInfo::new(dir_entry.file_name().to_string_lossy())?;
-            if log_info.date_time > app_state.latest_log_datetime() {
-                if log_info.date_time > latest_timestamp {
-                    // Save latest timestamp found
-                    latest_timestamp = log_info.date_time;
+            Ok(folder_logs) => {
+                if !folder_logs.unrecognised_files.is_empty() && folder.report_unrecognised_files()
+                {
+                    result.notices.push(format!(
+                        "{} unrecognised files present in {}: {:?}",
+                        folder_logs.unrecognised_files.len(),
+                        folder.display_name(),
+                        folder_logs.unrecognised_files
+                    ));
                 }
-                if log_info.abnormal_outcome.is_some() {
-                    log_info.extract_errors(&dir_entry.path())?;
-                    result.push(log_info);
+                result.notices.extend(folder_logs.notes);
+                if !folder_logs.late_logs.is_empty() {
+                    result.notices.push(format!(
+                        "{} logs arrived after newer logs were processed in {}: {:?}",
+                        folder_logs.late_logs.len(),
+                        folder.display_name(),
+                        folder_logs.late_logs
+                    ));
                 }
+                result.log_infos.extend(folder_logs.log_infos);
+                result.seen_logs.push(folder_logs.seen_logs);
+            }
+            Err(e) => {
+                result.failures.push(e);
+                result.seen_logs.push(Vec::new());
             }
         }
     }
-    if latest_timestamp > app_state.latest_log_datetime() {
-        app_state.set_latest_log_datetime(latest_timestamp);
-    }
 
     // Sort output to show errors in age order
-    result.sort_by_key(|x| x.date_time);
+    result.log_infos.sort_by_key(|x| x.date_time);
+
+    result
+}
+
+/// Returns a list of the new logs with errors
+///
+/// If a minimum severity is set then every new log is parsed and returned if it is at least that
+/// severe, otherwise only the logs with an outcome in the filename are returned. Files excluded by
+/// the folder's patterns are ignored and files that are not recognised as logs are skipped.
+/// Subfolders are searched up to the folder's maximum depth. Logs that are older than the latest
+/// log processed but were not processed themselves (within the folder's lookback window) are also new.
+pub fn process_logs_folder(
+    folder: &WatchedFolder,
+    min_severity: Option<Severity>,
+) -> anyhow::Result<FolderLogs> {
+    let mut result = FolderLogs::default();
+    let file_patterns = folder.file_patterns()?;
+    let mut found_files = Vec::new();
+    let mut visited_dirs = HashSet::new();
+    find_files(
+        folder,
+        folder.logs_dir(),
+        Path::new(""),
+        &mut visited_dirs,
+        &mut found_files,
+    )?;
+    for FoundFile { path, sub_dir } in found_files {
+        let Some(file_name) = path.file_name() else {
+            continue; // Not expected as it came from a directory listing
+        };
+        let file_name = file_name.to_string_lossy();
+        if !file_patterns.is_match(&file_name) {
+            continue;
+        }
+        let Ok(mut log_info) = LogInfo::new(&file_name) else {
+            result.unrecognised_files.push(path);
+            continue;
+        };
+        log_info.source = source_name(folder.display_name(), &sub_dir);
+        log_info.path = Some(path.clone());
+        let identity = LogIdentity {
+            date_time: log_info.date_time,
+            path: sub_dir.join(file_name.as_ref()),
+            size: fs::metadata(&path)
+                .with_context(|| format!("failed to get size of log: {path:?}"))?
+                .len(),
+        };
+        if !folder.is_unprocessed(&identity) {
+            continue;
+        }
+        let is_late = log_info.date_time <= folder.latest_log_datetime();
+        result.seen_logs.push(SeenLog {
+            profile: log_info.profile.clone(),
+            identity,
+        });
+        let is_reported = match min_severity {
+            Some(min_severity) => {
+                result.notes.extend(log_info.extract_details(&path)?);
+                log_info.severity() >= min_severity
+            }
+            None => {
+                let has_outcome = log_info.abnormal_outcome.is_some();
+                if has_outcome {
+                    result.notes.extend(log_info.extract_details(&path)?);
+                }
+                has_outcome
+            }
+        };
+        if is_reported {
+            if is_late {
+                result.late_logs.push(path);
+            }
+            result.log_infos.push(log_info);
+        }
+    }
+    // Sort output to show errors in age order
+    result.log_infos.sort_by_key(|x| x.date_time);
 
     Ok(result)
 }
 
+/// A file found while searching a watched folder
+struct FoundFile {
+    path: PathBuf,
+    /// Relative to the watched folder (empty for files directly in the watched folder)
+    sub_dir: PathBuf,
+}
+
+/// Adds all the files in `dir` to `found_files` and searches subfolders if the folder's maximum
+/// depth is not yet reached
+fn find_files(
+    folder: &WatchedFolder,
+    dir: &Path,
+    sub_dir: &Path,
+    visited_dirs: &mut HashSet<PathBuf>,
+    found_files: &mut Vec<FoundFile>,
+) -> anyhow::Result<()> {
+    // Prevent loops and searching the same folder twice if symlinks are followed
+    let canonical_dir = dir
+        .canonicalize()
+        .with_context(|| format!("failed to get canonical version of: {dir:?}"))?;
+    if !visited_dirs.insert(canonical_dir) {
+        return Ok(());
+    }
+
+    for dir_entry in read_dir(dir).with_context(|| format!("failed to read log folder: {dir:?}"))? {
+        let dir_entry =
+            dir_entry.with_context(|| format!("failed to read entry in folder: {dir:?}"))?;
+        let path = dir_entry.path();
+        let mut file_type = dir_entry
+            .file_type()
+            .with_context(|| format!("failed to get file type for: {path:?}"))?;
+        if file_type.is_symlink() {
+            match folder.symlink_policy() {
+                SymlinkPolicy::Ignore => continue,
+                SymlinkPolicy::Follow => match fs::metadata(&path) {
+                    Ok(metadata) => file_type = metadata.file_type(),
+                    Err(e) => {
+                        eprintln!("skipping broken symlink {path:?}: {e}");
+                        continue;
+                    }
+                },
+            }
+        }
+
+        if file_type.is_file() {
+            found_files.push(FoundFile {
+                path,
+                sub_dir: sub_dir.to_path_buf(),
+            });
+        } else if file_type.is_dir() && sub_dir.components().count() < folder.max_depth() {
+            let child_sub_dir = sub_dir.join(dir_entry.file_name());
+            find_files(folder, &path, &child_sub_dir, visited_dirs, found_files)?;
+        }
+    }
+    Ok(())
+}
+
+/// The name to show for logs found in `sub_dir` of the watched folder
+fn source_name(display_name: &str, sub_dir: &Path) -> String {
+    let mut result = display_name.to_string();
+    for component in sub_dir.components() {
+        result.push('/');
+        result.push_str(&component.as_os_str().to_string_lossy());
+    }
+    result
+}
+
 /// Converts the input into it's canonical form and based on the assumption that it is a file also returns the parent folder
 fn get_canonical_folder_and_filename<P: AsRef<Path>>(
     file_path: P,
@@ -1,28 +1,78 @@
+mod text;
+
 use std::{
     fmt::Display,
     fs,
-    io::{self, BufRead as _},
-    ops::ControlFlow,
-    path::Path,
+    io::Read as _,
+    path::{Path, PathBuf},
+    str::FromStr,
     sync::OnceLock,
+    time::Duration,
 };
 
 use anyhow::{anyhow, bail, Context};
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, NaiveTime};
+use ego_tree::NodeRef;
+use flate2::read::GzDecoder;
 use regex::Regex;
+use scraper::{ElementRef, Html, Node, Selector};
+use zip::ZipArchive;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
 pub struct LogInfo {
+    /// Name of the watched folder the log was found in (empty if not from a watched folder)
+    pub source: String,
+    /// Name of the FreeFileSync profile (batch job) that wrote the log, taken from the start of the filename
+    pub profile: String,
     pub date_time: NaiveDateTime,
     pub abnormal_outcome: Option<String>,
-    pub errors_and_warnings: Vec<String>,
+    pub summary: Option<Summary>,
+    pub errors_and_warnings: Vec<LogEntry>,
+    /// Where the log file was found (not set if not from a watched folder)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+/// A row from the "Errors and warnings" table of the log
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub time: NaiveTime,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Ordered from least to most severe
+#[derive(
+    Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Statistics from the summary table at the top of the log
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq)]
+pub struct Summary {
+    pub errors: u64,
+    pub warnings: u64,
+    pub items_processed: ItemStats,
+    pub items_remaining: ItemStats,
+    pub total_time: Duration,
+}
+
+/// Number of items and their size as shown in the summary table (size is only as precise as the displayed value)
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct ItemStats {
+    pub count: u64,
+    pub bytes: u64,
 }
 
 impl LogInfo {
     pub fn new<S: AsRef<str>>(file_name: S) -> anyhow::Result<Self> {
         static CELL_RE: OnceLock<Regex> = OnceLock::new();
         let re = CELL_RE.get_or_init(|| {
-            Regex::new(r"(\d\d\d\d-\d\d-\d\d \d\d\d\d\d\d)\.\d\d\d ?(\[.+\])?\.html")
+            Regex::new(r"^(?:(.*) )?(\d\d\d\d-\d\d-\d\d \d\d\d\d\d\d)\.\d\d\d ?(\[.+\])?\.(?:html|log|html\.gz|log\.gz|html\.zip|log\.zip|gz|zip)$")
                 .expect("failed to compile regex")
         });
 
@@ -30,162 +80,478 @@ impl LogInfo {
             // Assumption: Only log files are present in the log folder
             bail!("regex failed to match filename: {}", file_name.as_ref())
         };
-        // Regex matched and can only match if first capture group is found as it is not optional
-        let date_time_str = captures.get(1).expect("required for match").as_str();
-        let abnormal_outcome = captures.get(2).map(|x| x.as_str().to_string());
+        // Regex matched and can only match if the date capture group is found as it is not optional
+        let profile = captures
+            .get(1)
+            .map(|x| x.as_str().to_string())
+            .unwrap_or_default();
+        let date_time_str = captures.get(2).expect("required for match").as_str();
+        let abnormal_outcome = captures.get(3).map(|x| x.as_str().to_string());
         let date_time = NaiveDateTime::parse_from_str(date_time_str, "%F %H%M%S")
             .with_context(|| format!("failed to parse date from {date_time_str:?}"))?;
         Ok(Self {
+            source: Default::default(),
+            profile,
             date_time,
             abnormal_outcome,
+            summary: None,
             errors_and_warnings: Default::default(),
+            path: None,
         })
     }
 
-    /// Expects to receive the input without the surrounding tags but including inner tags to be replaced
-    fn add_error_or_warning(&mut self, msg: String) {
-        let msg = msg.replace("&quot;", "\"");
-        let msg = msg.replace("<br>", "; ");
-        self.errors_and_warnings.push(msg);
-    }
-
-    pub fn extract_errors(&mut self, file_path: &Path) -> anyhow::Result<()> {
-        let mut extract_state = ExtractState::FindTableStart;
-        for line in read_lines(file_path)? {
-            let line = line.with_context(|| format!("failed to read line in {:?}", file_path))?;
-            extract_state = match extract_state {
-                ExtractState::FindTableStart => find_start_of_table(&line),
-                ExtractState::FindMsg => match self.check_for_start_of_msg(line) {
-                    ControlFlow::Continue(value) => value,
-                    ControlFlow::Break(value) => return value,
-                },
-                ExtractState::ReadingMsg(count, partial_msg) => {
-                    self.continue_reading_msg(count, partial_msg, line)?
-                }
+    /// The most severe of the outcome in the filename and the entries in "Errors and warnings"
+    ///
+    /// A log without either is [`Severity::Info`] as it only shows that the sync ran
+    pub fn severity(&self) -> Severity {
+        let outcome_severity = match self.abnormal_outcome.as_deref() {
+            None => Severity::Info,
+            Some("[Warning]") => Severity::Warning,
+            Some(_) => Severity::Error,
+        };
+        self.errors_and_warnings
+            .iter()
+            .map(|x| x.severity)
+            .fold(outcome_severity, Ord::max)
+    }
+
+    /// Number of entries in "Errors and warnings" with exactly this severity
+    pub fn count_of(&self, severity: Severity) -> usize {
+        self.errors_and_warnings
+            .iter()
+            .filter(|x| x.severity == severity)
+            .count()
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.count_of(Severity::Error)
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.count_of(Severity::Warning)
+    }
+
+    /// Entries in "Errors and warnings" that are at least as severe as `min_severity`
+    pub fn entries_at_least(&self, min_severity: Severity) -> impl Iterator<Item = &LogEntry> {
+        self.errors_and_warnings
+            .iter()
+            .filter(move |x| x.severity >= min_severity)
+    }
+
+    /// Reads the log file and extracts the summary and the "Errors and warnings" table
+    ///
+    /// Supports HTML and plain text logs either uncompressed or compressed with gzip or zip. If the
+    /// summary or the entries cannot be extracted they are left empty and a note saying why is
+    /// returned instead of failing, so that a change to the log format does not prevent the log
+    /// from being reported. Finding no entries when the summary says there are some is treated as
+    /// failing to extract them.
+    pub fn extract_details(&mut self, file_path: &Path) -> anyhow::Result<Vec<String>> {
+        let mut notes = Vec::new();
+        let contents = read_log_contents(file_path)?;
+        let (summary, errors_and_warnings) = if is_html(&contents) {
+            let document = Html::parse_document(&contents);
+            (
+                extract_summary(&document),
+                extract_errors_and_warnings(&document),
+            )
+        } else {
+            (
+                text::extract_summary(&contents),
+                text::extract_errors_and_warnings(&contents),
+            )
+        };
+        self.summary = match summary {
+            Ok(summary) => Some(summary),
+            Err(e) => {
+                notes.push(format!(
+                    "failed to extract summary from {file_path:?}: {e:#}"
+                ));
+                None
             }
-        }
-        bail!("unexpected end of file")
-    }
-
-    fn check_for_start_of_msg(
-        &mut self,
-        line: String,
-    ) -> ControlFlow<anyhow::Result<()>, ExtractState> {
-        let line_trimmed = line.trim_start();
-        if !line_trimmed.starts_with("<td>") {
-            // Not the start of the message check for end of table or go to next line
-            if line_trimmed.starts_with("</table>") {
-                ControlFlow::Break(Ok(()))
-            } else {
-                // Go to next line
-                ControlFlow::Continue(ExtractState::FindMsg)
+        };
+        let errors_and_warnings = errors_and_warnings.and_then(|entries| match &self.summary {
+            Some(summary) if entries.is_empty() && summary.errors + summary.warnings > 0 => {
+                bail!(
+                    "summary has {} errors and {} warnings but no entries were found",
+                    summary.errors,
+                    summary.warnings
+                )
             }
-        } else {
-            // Check for case of single line
-            static CELL_RE_SINGLE_LINE: OnceLock<Regex> = OnceLock::new();
-            let re = CELL_RE_SINGLE_LINE
-                .get_or_init(|| Regex::new(r"<td>(.+)<\/td>").expect("failed to compile regex"));
-
-            if let Some(captures) = re.captures(&line) {
-                // Single line found
-                self.add_error_or_warning(
-                    captures
-                        .get(1)
-                        .expect("required for match")
-                        .as_str()
-                        .to_string(),
-                );
-                ControlFlow::Continue(ExtractState::FindMsg) // Look for next msg
-            } else {
-                // First part of a multiline message
-                static CELL_RE_START_OF_MSG: OnceLock<Regex> = OnceLock::new();
-                let re = CELL_RE_START_OF_MSG
-                    .get_or_init(|| Regex::new(r"<td>(.+)").expect("failed to compile regex"));
-                if let Some(captures) = re.captures(&line) {
-                    // Single line found
-                    let first_part_of_msg = captures
-                        .get(1)
-                        .expect("required for match")
-                        .as_str()
-                        .to_string();
-                    ControlFlow::Continue(ExtractState::ReadingMsg(1, first_part_of_msg))
-                } else {
-                    ControlFlow::Break(Err(anyhow!("something wrong with the logic, we checked that this line starts a message but then... it doesn't now?")))
-                }
+            _ => Ok(entries),
+        });
+        self.errors_and_warnings = match errors_and_warnings {
+            Ok(entries) => entries,
+            Err(e) => {
+                notes.push(format!(
+                    "failed to extract errors and warnings from {file_path:?}: {e:#}"
+                ));
+                Vec::new()
             }
+        };
+        Ok(notes)
+    }
+}
+
+/// Returns the contents of the log decompressing it first based on the file extension if needed
+fn read_log_contents(file_path: &Path) -> anyhow::Result<String> {
+    let file_name = file_path
+        .file_name()
+        .map(|x| x.to_string_lossy())
+        .unwrap_or_default();
+    let mut result = String::new();
+    if file_name.ends_with(".gz") {
+        let file = fs::File::open(file_path)
+            .with_context(|| format!("failed to open file: {file_path:?}"))?;
+        GzDecoder::new(file)
+            .read_to_string(&mut result)
+            .with_context(|| format!("failed to decompress gzip file: {file_path:?}"))?;
+    } else if file_name.ends_with(".zip") {
+        let file = fs::File::open(file_path)
+            .with_context(|| format!("failed to open file: {file_path:?}"))?;
+        let mut archive = ZipArchive::new(file)
+            .with_context(|| format!("failed to read zip file: {file_path:?}"))?;
+        // Prefer a file that looks like a log but fall back to the first file if none do
+        let is_file = |name: &&str| !name.ends_with('/');
+        let entry_name = archive
+            .file_names()
+            .filter(is_file)
+            .find(|name| name.ends_with(".html") || name.ends_with(".log"))
+            .or_else(|| archive.file_names().find(is_file))
+            .with_context(|| format!("no files found in zip file: {file_path:?}"))?
+            .to_string();
+        archive
+            .by_name(&entry_name)
+            .with_context(|| format!("failed to find {entry_name:?} in zip file: {file_path:?}"))?
+            .read_to_string(&mut result)
+            .with_context(|| {
+                format!("failed to decompress {entry_name:?} from zip file: {file_path:?}")
+            })?;
+    } else {
+        result = fs::read_to_string(file_path)
+            .with_context(|| format!("failed to read file: {file_path:?}"))?;
+    }
+    Ok(result)
+}
+
+/// HTML logs start with a tag (possibly after a byte order mark) while text logs do not
+fn is_html(contents: &str) -> bool {
+    contents
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with('<')
+}
+
+fn extract_summary(document: &Html) -> anyhow::Result<Summary> {
+    static ROW_SELECTOR: OnceLock<Selector> = OnceLock::new();
+    let row_selector = ROW_SELECTOR.get_or_init(|| {
+        Selector::parse("table.summary-table tr").expect("failed to parse selector")
+    });
+
+    let mut rows = document.select(row_selector).peekable();
+    if rows.peek().is_none() {
+        bail!("summary table not found")
+    }
+    let mut summary = Summary::default();
+    for row in rows {
+        let cells: Vec<ElementRef> = row.select(td_selector()).collect();
+        let [label, _icon, value] = cells[..] else {
+            bail!(
+                "expected 3 cells in summary table row but found {}: {:?}",
+                cells.len(),
+                row.html()
+            )
+        };
+        let (main_value, size) = split_summary_value(value);
+        let main_value = main_value.as_str();
+        let size = size.as_deref();
+        match element_text(label).trim_end_matches(':') {
+            "Errors" => summary.errors = parse_count(main_value)?,
+            "Warnings" => summary.warnings = parse_count(main_value)?,
+            "Items processed" => summary.items_processed = parse_item_stats(main_value, size)?,
+            "Items remaining" => summary.items_remaining = parse_item_stats(main_value, size)?,
+            "Total time" => summary.total_time = parse_total_time(main_value)?,
+            _ => {} // Ignore rows that are not used
         }
     }
+    Ok(summary)
+}
 
-    fn continue_reading_msg(
-        &mut self,
-        count: u8,
-        mut partial_msg: String,
-        line: String,
-    ) -> anyhow::Result<ExtractState> {
-        static CELL_RE_LAST_LINE: OnceLock<Regex> = OnceLock::new();
-        let re = CELL_RE_LAST_LINE
-            .get_or_init(|| Regex::new(r"(.*)<\/td>").expect("failed to compile regex"));
-
-        if let Some(captures) = re.captures(&line) {
-            partial_msg.push_str(
-                &captures
-                    .get(1)
-                    .map(|x| x.as_str().to_string())
-                    .unwrap_or_default(),
-            );
-            self.add_error_or_warning(partial_msg);
-            Ok(ExtractState::FindMsg)
-        } else {
-            // Not last line add value and keep reading
-            if count >= ExtractState::MAX_MSG_LINES {
-                bail!("something is wrong found too many lines in msg. Past {} which was with line: {line:?}", ExtractState::MAX_MSG_LINES)
-            }
-            partial_msg.push_str(&line);
-            Ok(ExtractState::ReadingMsg(count + 1, partial_msg))
+/// Returns the error and warning rows of the first `log-items` table
+///
+/// When there are errors or warnings the first table only lists them, otherwise it is the full log
+/// which is why only the rows that are at least warnings are kept. If there is no table an empty
+/// list is returned.
+fn extract_errors_and_warnings(document: &Html) -> anyhow::Result<Vec<LogEntry>> {
+    static TABLE_SELECTOR: OnceLock<Selector> = OnceLock::new();
+    static ROW_SELECTOR: OnceLock<Selector> = OnceLock::new();
+    static IMG_SELECTOR: OnceLock<Selector> = OnceLock::new();
+    let table_selector = TABLE_SELECTOR
+        .get_or_init(|| Selector::parse("table.log-items").expect("failed to parse selector"));
+    let row_selector =
+        ROW_SELECTOR.get_or_init(|| Selector::parse("tr").expect("failed to parse selector"));
+    let img_selector =
+        IMG_SELECTOR.get_or_init(|| Selector::parse("img").expect("failed to parse selector"));
+
+    let Some(table) = document.select(table_selector).next() else {
+        return Ok(Vec::new());
+    };
+
+    let mut result = Vec::new();
+    for row in table.select(row_selector) {
+        let cells: Vec<ElementRef> = row.select(td_selector()).collect();
+        let [time, severity, msg] = cells[..] else {
+            bail!(
+                "expected 3 cells in log items row but found {}: {:?}",
+                cells.len(),
+                row.html()
+            )
+        };
+        let time = parse_entry_time(&element_text(time))?;
+        let severity = severity
+            .select(img_selector)
+            .find_map(|x| x.value().attr("alt"))
+            .ok_or_else(|| anyhow!("severity icon not found in row: {:?}", row.html()))?
+            .trim()
+            .trim_end_matches(':')
+            .parse()?;
+        if severity >= Severity::Warning {
+            result.push(LogEntry {
+                time,
+                severity,
+                message: message_text(msg),
+            });
         }
     }
+    Ok(result)
 }
 
-fn find_start_of_table(line: &str) -> ExtractState {
-    static CELL_RE_TABLE_START: OnceLock<Regex> = OnceLock::new();
-    let re = CELL_RE_TABLE_START.get_or_init(|| {
-        Regex::new(r"<div.*Errors and warnings:").expect("failed to compile regex")
+/// Converts the contents of the message cell into a single line of plain text
+///
+/// Entities are already decoded by the parser, inline markup is stripped and line breaks (`<br>` or
+/// block elements) are replaced by "; ". Whitespace (including `&nbsp;`) is collapsed to single spaces.
+fn message_text(cell: ElementRef) -> String {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    collect_message_lines(*cell, &mut lines, &mut current);
+    push_message_line(&mut lines, &mut current);
+    lines.join("; ")
+}
+
+fn collect_message_lines(node: NodeRef<Node>, lines: &mut Vec<String>, current: &mut String) {
+    for child in node.children() {
+        match child.value() {
+            Node::Text(text) => current.push_str(&text.text),
+            Node::Element(element) => match element.name() {
+                "br" => push_message_line(lines, current),
+                "script" | "style" => {} // Not part of the message
+                "div" | "p" | "li" | "tr" | "table" | "ul" | "ol" | "pre" => {
+                    push_message_line(lines, current);
+                    collect_message_lines(child, lines, current);
+                    push_message_line(lines, current);
+                }
+                _ => collect_message_lines(child, lines, current),
+            },
+            _ => {} // Comments and similar are not displayed
+        }
+    }
+}
+
+/// Adds the line collected so far (if not blank) and clears `current` for the next line
+fn push_message_line(lines: &mut Vec<String>, current: &mut String) {
+    let line = current.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    current.clear();
+}
+
+fn td_selector() -> &'static Selector {
+    static TD_SELECTOR: OnceLock<Selector> = OnceLock::new();
+    TD_SELECTOR.get_or_init(|| Selector::parse("td").expect("failed to parse selector"))
+}
+
+/// All the text inside of the element with surrounding whitespace removed
+fn element_text(element: ElementRef) -> String {
+    element.text().collect::<String>().trim().to_string()
+}
+
+/// Splits a value cell into the bold main value and the optional size in brackets after it
+///
+/// For example `<span style="font-weight:600;">61</span> (27.3 MB)` becomes `("61", Some("27.3 MB"))`
+fn split_summary_value(value: ElementRef) -> (String, Option<String>) {
+    static SPAN_SELECTOR: OnceLock<Selector> = OnceLock::new();
+    static SIZE_RE: OnceLock<Regex> = OnceLock::new();
+    let span_selector =
+        SPAN_SELECTOR.get_or_init(|| Selector::parse("span").expect("failed to parse selector"));
+    let size_re =
+        SIZE_RE.get_or_init(|| Regex::new(r"\((.*?)\)").expect("failed to compile regex"));
+
+    let full_text = element_text(value);
+    let main_value = value
+        .select(span_selector)
+        .next()
+        .map(element_text)
+        .unwrap_or_else(|| full_text.clone());
+    let size = size_re.captures(&full_text).map(|x| {
+        x.get(1)
+            .expect("required for match")
+            .as_str()
+            .trim()
+            .to_string()
     });
-    if re.is_match(line) {
-        ExtractState::FindMsg
+    (main_value, size)
+}
+
+/// Parses the time shown on each row of the log (eg. "09:30:19 AM" or "14:12:18")
+fn parse_entry_time(value: &str) -> anyhow::Result<NaiveTime> {
+    NaiveTime::parse_from_str(value, "%I:%M:%S %p")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+        .with_context(|| format!("failed to parse time of log entry from {value:?}"))
+}
+
+/// Parses counts which may include thousands separators (eg. "1,890,726")
+fn parse_count(value: &str) -> anyhow::Result<u64> {
+    let digits: String = value.chars().filter(|c| c.is_ascii_digit()).collect();
+    digits
+        .parse()
+        .with_context(|| format!("failed to parse count from {value:?}"))
+}
+
+fn parse_item_stats(count: &str, size: Option<&str>) -> anyhow::Result<ItemStats> {
+    Ok(ItemStats {
+        count: parse_count(count)?,
+        bytes: size.map(parse_size).transpose()?.unwrap_or_default(),
+    })
+}
+
+/// Parses sizes as displayed by FreeFileSync (eg. "0 bytes", "914 KB", "27.3 MB")
+fn parse_size(value: &str) -> anyhow::Result<u64> {
+    let Some((number, unit)) = value.trim().split_once(' ') else {
+        bail!("failed to find unit in size: {value:?}")
+    };
+    let multiplier: u64 = match unit.trim() {
+        "byte" | "bytes" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        "TB" => 1 << 40,
+        "PB" => 1 << 50,
+        other => bail!("unknown size unit {other:?} in {value:?}"),
+    };
+    let number: f64 = number
+        .replace(',', "")
+        .parse()
+        .with_context(|| format!("failed to parse number in size: {value:?}"))?;
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+/// Parses the total time in the format "HH:MM:SS"
+fn parse_total_time(value: &str) -> anyhow::Result<Duration> {
+    let parts = value
+        .split(':')
+        .map(|x| x.trim().parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("failed to parse total time from {value:?}"))?;
+    let [hours, minutes, seconds] = parts[..] else {
+        bail!("expected total time to have 3 parts but got {value:?}")
+    };
+    Ok(Duration::from_secs(hours * 3600 + minutes * 60 + seconds))
+}
+
+/// Formats the size similar to how FreeFileSync displays them (3 significant digits)
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KB", "MB", "GB", "TB", "PB"];
+    if bytes < 1024 {
+        return format!("{bytes} bytes");
+    }
+    let mut value = bytes as f64;
+    let mut unit = UNITS[0];
+    for next_unit in UNITS {
+        value /= 1024.0;
+        unit = next_unit;
+        if value < 1024.0 {
+            break;
+        }
+    }
+    if value >= 100.0 {
+        format!("{value:.0} {unit}")
+    } else if value >= 10.0 {
+        format!("{value:.1} {unit}")
     } else {
-        ExtractState::FindTableStart
+        format!("{value:.2} {unit}")
     }
 }
 
-impl Display for LogInfo {
+impl Display for Summary {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total_secs = self.total_time.as_secs();
         write!(
             f,
-            "{} {} errors: {}\n{}\n",
-            self.abnormal_outcome.as_deref().unwrap_or("[ - ]"),
-            self.date_time.format("%F %H:%M:%S"),
-            self.errors_and_warnings.len(),
-            self.errors_and_warnings.join("\n")
+            "Processed {} items ({}) in {:02}:{:02}:{:02}, {} items ({}) remaining",
+            self.items_processed.count,
+            format_size(self.items_processed.bytes),
+            total_secs / 3600,
+            total_secs / 60 % 60,
+            total_secs % 60,
+            self.items_remaining.count,
+            format_size(self.items_remaining.bytes),
         )
     }
 }
 
-// The output is wrapped in a Result to allow matching on errors
-// Returns an Iterator to the Reader of the lines of the file.
-fn read_lines<P: AsRef<Path>>(path: P) -> anyhow::Result<io::Lines<io::BufReader<fs::File>>> {
-    let file = fs::File::open(&path)
-        .with_context(|| format!("failed to open file: {:?}", path.as_ref()))?;
-    Ok(io::BufReader::new(file).lines())
+impl FromStr for Severity {
+    type Err = anyhow::Error;
+
+    /// Parses the alt text of the icon FreeFileSync uses for the severity (without the trailing colon)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "Info" => Self::Info,
+            "Warning" => Self::Warning,
+            "Error" => Self::Error,
+            other => bail!("unknown severity: {other:?}"),
+        })
+    }
 }
 
-enum ExtractState {
-    FindTableStart,
-    FindMsg,
-    ReadingMsg(u8, String),
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Severity::Info => "Info",
+            Severity::Warning => "Warning",
+            Severity::Error => "Error",
+        };
+        write!(f, "{s}")
+    }
 }
 
-impl ExtractState {
-    /// Detect error if message is more than 5 lines long. Actually expecting exactly 2.
-    const MAX_MSG_LINES: u8 = 5;
+impl Display for LogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+impl Display for LogInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.source.is_empty() {
+            write!(f, "{} ", self.source)?;
+        }
+        if !self.profile.is_empty() {
+            write!(f, "{} ", self.profile)?;
+        }
+        writeln!(
+            f,
+            "{} {} errors: {} warnings: {}",
+            self.abnormal_outcome.as_deref().unwrap_or("[ - ]"),
+            self.date_time.format("%F %H:%M:%S"),
+            self.error_count(),
+            self.warning_count(),
+        )?;
+        if let Some(summary) = &self.summary {
+            writeln!(f, "{summary}")?;
+        }
+        for entry in self.errors_and_warnings.iter() {
+            writeln!(f, "{entry}")?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,94 @@
+//! Extraction from the plain text logs written by older versions of FreeFileSync
+//!
+//! These logs contain the summary as lines starting with "|" followed by every message in the log.
+//! Unlike the HTML logs there is no separate "Errors and warnings" table so it is built by keeping
+//! only the messages that are warnings or errors.
+
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context};
+use regex::Regex;
+
+use super::{
+    parse_count, parse_entry_time, parse_item_stats, parse_total_time, LogEntry, Severity, Summary,
+};
+
+pub(super) fn extract_summary(contents: &str) -> anyhow::Result<Summary> {
+    static SUMMARY_LINE_RE: OnceLock<Regex> = OnceLock::new();
+    static VALUE_RE: OnceLock<Regex> = OnceLock::new();
+    let summary_line_re = SUMMARY_LINE_RE.get_or_init(|| {
+        Regex::new(
+            r"^\|?\s*(Errors|Warnings|Items processed|Items remaining|Total time):\s*(.+?)\s*$",
+        )
+        .expect("failed to compile regex")
+    });
+    let value_re = VALUE_RE
+        .get_or_init(|| Regex::new(r"^(.*?)\s*(?:\((.*)\))?$").expect("failed to compile regex"));
+
+    let mut is_summary_found = false;
+    let mut summary = Summary::default();
+    for line in contents.lines() {
+        let Some(captures) = summary_line_re.captures(line) else {
+            continue;
+        };
+        is_summary_found = true;
+        let value = captures.get(2).expect("required for match").as_str();
+        let value_captures = value_re
+            .captures(value)
+            .with_context(|| format!("failed to split summary value: {value:?}"))?;
+        let main_value = value_captures.get(1).expect("required for match").as_str();
+        let size = value_captures.get(2).map(|x| x.as_str());
+        match captures.get(1).expect("required for match").as_str() {
+            "Errors" => summary.errors = parse_count(main_value)?,
+            "Warnings" => summary.warnings = parse_count(main_value)?,
+            "Items processed" => summary.items_processed = parse_item_stats(main_value, size)?,
+            "Items remaining" => summary.items_remaining = parse_item_stats(main_value, size)?,
+            "Total time" => summary.total_time = parse_total_time(main_value)?,
+            _ => unreachable!("regex only matches the labels above"),
+        }
+    }
+    if !is_summary_found {
+        bail!("summary not found")
+    }
+    Ok(summary)
+}
+
+/// Messages that span multiple lines have the following lines indented, these are joined with "; "
+pub(super) fn extract_errors_and_warnings(contents: &str) -> anyhow::Result<Vec<LogEntry>> {
+    static ENTRY_RE: OnceLock<Regex> = OnceLock::new();
+    let entry_re = ENTRY_RE.get_or_init(|| {
+        Regex::new(r"^\[?(\d?\d:\d\d:\d\d(?: [AP]M)?)\]?\s+(Info|Warning|Error):\s*(.*?)\s*$")
+            .expect("failed to compile regex")
+    });
+
+    let mut result = Vec::new();
+    let mut current: Option<LogEntry> = None;
+    for line in contents.lines() {
+        if let Some(captures) = entry_re.captures(line) {
+            result.extend(current.take());
+            current = Some(LogEntry {
+                time: parse_entry_time(captures.get(1).expect("required for match").as_str())?,
+                severity: captures
+                    .get(2)
+                    .expect("required for match")
+                    .as_str()
+                    .parse()?,
+                message: captures
+                    .get(3)
+                    .expect("required for match")
+                    .as_str()
+                    .to_string(),
+            });
+        } else if let Some(entry) = current.as_mut() {
+            if line.starts_with(char::is_whitespace) && !line.trim().is_empty() {
+                entry.message.push_str("; ");
+                entry.message.push_str(line.trim());
+            } else {
+                result.extend(current.take());
+            }
+        }
+    }
+    result.extend(current);
+    result.retain(|x| x.severity >= Severity::Warning);
+    Ok(result)
+}
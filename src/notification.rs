@@ -1,13 +1,212 @@
-use std::path::Path;
+use std::{path::Path, time::Duration};
 
+use anyhow::{anyhow, bail};
+use reqwest::{header::HeaderMap, StatusCode};
+use retry::RetryHint;
+
+use crate::{LogInfo, Severity};
+
+mod config;
 mod discord;
 mod email;
+mod push;
+mod retry;
+mod slack;
+mod webhook;
+
+pub use config::{ChannelConfig, ChannelKind, ChannelMode, NotificationConfig};
+pub use discord::{Discord, DiscordConfig};
+pub use email::{Email, EmailConfig, EmailRoute, EmailTransport, SmtpAuth, TlsMode};
+pub use push::{Gotify, GotifyConfig, Ntfy, NtfyConfig};
+pub use retry::RetryPolicy;
+pub use slack::{Slack, SlackConfig};
+pub use webhook::{Webhook, WebhookConfig};
+
+/// A backend that is able to deliver messages (eg. Discord or email)
+pub trait Notifier {
+    /// Used to identify the channel in errors
+    fn name(&self) -> &str;
+
+    fn send(&self, msg: &Message) -> anyhow::Result<()>;
+}
+
+/// What gets sent to the channels
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Message {
+    pub severity: Severity,
+    /// Plain text version of the message, used by channels that do not build their own from `log_infos`
+    pub text: String,
+    /// The logs that the message is about (if any)
+    #[serde(default)]
+    pub log_infos: Vec<LogInfo>,
+    /// Lines of the message that are not about a specific log (eg. folders that failed to process)
+    #[serde(default)]
+    pub notes: Vec<String>,
+}
+
+impl Message {
+    pub fn new<S: Into<String>>(severity: Severity, text: S) -> Self {
+        Self {
+            severity,
+            text: text.into(),
+            log_infos: Default::default(),
+            notes: Default::default(),
+        }
+    }
+}
+
+/// A notifier with the settings for when it should be used
+pub struct Channel {
+    pub mode: ChannelMode,
+    /// If set the channel is skipped for messages that are less severe
+    pub min_severity: Option<Severity>,
+    pub notifier: Box<dyn Notifier>,
+}
+
+impl Channel {
+    fn accepts(&self, msg: &Message) -> bool {
+        self.min_severity.is_none_or(|x| msg.severity >= x)
+    }
+}
+
+/// Sends the message to every channel regardless of mode and severity to confirm they all work
+pub fn send_test_notification(msg: &Message, config_folder: &Path) -> anyhow::Result<()> {
+    let channels = NotificationConfig::load(config_folder)?.build_channels(config_folder);
+    let mut failed_count = 0;
+    for channel in channels.iter() {
+        if let Err(e) = channel.notifier.send(msg) {
+            eprintln!("{e:?}");
+            failed_count += 1;
+        }
+    }
+    if failed_count > 0 {
+        bail!(
+            "failed to send test notification to {failed_count} of {} channels",
+            channels.len()
+        )
+    }
+    Ok(())
+}
 
-pub fn send_notification(msg: &str, config_folder: &Path) -> anyhow::Result<()> {
-    match discord::Discord::send(msg, config_folder) {
-        Ok(()) => return Ok(()),
-        Err(e) => eprintln!("{e:?}"),
+/// Sends to every [`ChannelMode::All`] channel and to the [`ChannelMode::Failover`] channels in
+/// order until one succeeds. Channels with a higher minimum severity than the message are skipped.
+///
+/// Succeeds if at least one channel delivered the message or if no channel accepted it
+pub fn send_to_channels(msg: &Message, channels: &[Channel]) -> anyhow::Result<()> {
+    let mut attempted_count = 0;
+    let mut delivered_count = 0;
+    let mut send = |channel: &Channel| {
+        attempted_count += 1;
+        match channel.notifier.send(msg) {
+            Ok(()) => {
+                delivered_count += 1;
+                true
+            }
+            Err(e) => {
+                eprintln!("{e:?}");
+                false
+            }
+        }
     };
 
-    email::Email::send(msg, config_folder)
+    let accepted_channels = || channels.iter().filter(|x| x.accepts(msg));
+    for channel in accepted_channels().filter(|x| x.mode == ChannelMode::All) {
+        send(channel);
+    }
+    for channel in accepted_channels().filter(|x| x.mode == ChannelMode::Failover) {
+        if send(channel) {
+            break;
+        }
+    }
+
+    if attempted_count > 0 && delivered_count == 0 {
+        bail!("failed to send notification via all {attempted_count} channels attempted")
+    }
+    Ok(())
+}
+
+/// Posts `body` as JSON to `url` and fails if the response is not a success
+fn post_json(url: &str, body: &serde_json::Value) -> anyhow::Result<()> {
+    check_response(post_json_unchecked(url, body)?)
+}
+
+/// Posts `body` as JSON to `url` without checking the response
+fn post_json_unchecked(
+    url: &str,
+    body: &serde_json::Value,
+) -> anyhow::Result<reqwest::blocking::Response> {
+    Ok(reqwest::blocking::Client::new()
+        .request(reqwest::Method::POST, url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()?)
+}
+
+/// Fails if the response is not a success
+///
+/// Client errors (other than timeouts and rate limiting) are marked as not worth retrying and
+/// rate limited responses are marked with the delay requested by the server
+fn check_response(resp: reqwest::blocking::Response) -> anyhow::Result<()> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let hint =
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            retry_after(resp.headers()).map(RetryHint::RetryAfter)
+        } else if status.is_client_error() && status != StatusCode::REQUEST_TIMEOUT {
+            Some(RetryHint::DoNotRetry)
+        } else {
+            None
+        };
+    const MAX_BODY_LEN: usize = 500;
+    let body = resp.text().unwrap_or_default();
+    let error = anyhow!(
+        "error response returned: {status} {}",
+        truncate(&body, MAX_BODY_LEN)
+    );
+    Err(match hint {
+        Some(hint) => error.context(hint),
+        None => error,
+    })
+}
+
+/// How long the server asked to wait before trying again, taken from the `Retry-After` header or
+/// Discord's `X-RateLimit-Reset-After` header
+///
+/// Only the number of seconds form of `Retry-After` is supported (fractions are allowed)
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    ["Retry-After", "X-RateLimit-Reset-After"]
+        .iter()
+        .find_map(|name| header_seconds(headers, name))
+}
+
+/// Reads a header that holds a number of seconds
+fn header_seconds(headers: &HeaderMap, name: &str) -> Option<Duration> {
+    let seconds: f64 = headers.get(name)?.to_str().ok()?.trim().parse().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Short description of the log made up of the source, profile and outcome
+fn log_title(log_info: &LogInfo) -> String {
+    let mut result = String::new();
+    for part in [&log_info.source, &log_info.profile] {
+        if !part.is_empty() {
+            result.push_str(part);
+            result.push(' ');
+        }
+    }
+    result.push_str(log_info.abnormal_outcome.as_deref().unwrap_or("[ - ]"));
+    result
+}
+
+/// Shortens `s` to at most `max_len` characters, marking it if anything was removed
+fn truncate(s: &str, max_len: usize) -> String {
+    const MARKER: &str = "…";
+    if s.chars().count() <= max_len {
+        return s.to_string();
+    }
+    let mut result: String = s.chars().take(max_len - MARKER.chars().count()).collect();
+    result.push_str(MARKER);
+    result
 }
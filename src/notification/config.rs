@@ -0,0 +1,220 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context};
+use chrono::TimeDelta;
+
+use super::{
+    Channel, Discord, DiscordConfig, Email, EmailConfig, Gotify, GotifyConfig, Message, Notifier,
+    Ntfy, NtfyConfig, RetryPolicy, Slack, SlackConfig, Webhook, WebhookConfig,
+};
+use crate::Severity;
+
+/// The channels to send notifications to, loaded from [`NotificationConfig::FILENAME`] in the config folder
+///
+/// Example:
+/// ```ron
+/// NotificationConfig(
+///     channels: [
+///         (kind: Discord((webhook_url: "https://discord.com/api/webhooks/..."))),
+///         (kind: Email((from_name: "FS Log Monitor", pass: "...", from_email: "monitor@example.com", to: ["it@example.com"], smtp_host: Some("smtp.example.com")))),
+///         (kind: Slack((webhook_url: "https://hooks.slack.com/services/..."))),
+///         (kind: Ntfy((server_url: "https://ntfy.sh", topic: "..."))),
+///         (kind: Webhook((url: "https://example.com/hook", body_template: r#"{"text": "{{severity}}: {{message}}"}"#))),
+///         (kind: Discord((webhook_url: "https://discord.com/api/webhooks/...")), mode: All, min_severity: Some(Error)),
+///         (kind: Gotify((server_url: "https://gotify.example.com", app_token: "...")), retry: (max_attempts: 5, initial_delay_secs: 10, backoff_factor: 2, jitter: 0.2)),
+///     ],
+/// )
+/// ```
+///
+/// If the file does not exist the legacy files are used instead (`d.data` for discord then `e.data` for email)
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationConfig {
+    pub channels: Vec<ChannelConfig>,
+    /// Messages that could not be delivered are retried on later runs until they are this old
+    #[serde(default = "NotificationConfig::default_outbox_max_age_hours")]
+    pub outbox_max_age_hours: u64,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelConfig {
+    pub kind: ChannelKind,
+    #[serde(default)]
+    pub mode: ChannelMode,
+    /// If set the channel is skipped for messages that are less severe
+    #[serde(default)]
+    pub min_severity: Option<Severity>,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+}
+
+/// How a channel is used relative to the other channels
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMode {
+    /// Always sent to
+    All,
+    /// Tried in the order they are listed until one succeeds
+    #[default]
+    Failover,
+}
+
+/// The backend used for a channel and its settings
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub enum ChannelKind {
+    Discord(DiscordConfig),
+    Email(EmailConfig),
+    Slack(SlackConfig),
+    Ntfy(NtfyConfig),
+    Gotify(GotifyConfig),
+    /// Any endpoint that accepts JSON, see [`WebhookConfig`]
+    Webhook(WebhookConfig),
+    /// Discord using the url suffix in `d.data` in the config folder
+    LegacyDiscord,
+    /// Email using the JSON settings in `e.data` in the config folder
+    LegacyEmail,
+}
+
+impl NotificationConfig {
+    pub const FILENAME: &str = "notifications.ron";
+
+    fn default_outbox_max_age_hours() -> u64 {
+        48
+    }
+
+    pub fn load(config_folder: &Path) -> anyhow::Result<Self> {
+        let filename = config_folder.join(Self::FILENAME);
+        if !filename.exists() {
+            return Ok(Self::legacy());
+        }
+        let s = fs::read_to_string(&filename)
+            .with_context(|| format!("failed to read notification config from {filename:?}"))?;
+        let result: Self = ron::from_str(&s).with_context(|| {
+            format!("failed to parse contents of {filename:?} as notification config")
+        })?;
+        if result.channels.is_empty() {
+            bail!("no notification channels set in {filename:?}");
+        }
+        for (i, channel) in result.channels.iter().enumerate() {
+            channel.retry.validate().with_context(|| {
+                format!(
+                    "invalid retry policy for channel #{} in {filename:?}",
+                    i + 1
+                )
+            })?;
+        }
+        result
+            .outbox_max_age()
+            .with_context(|| format!("invalid notification config in {filename:?}"))?;
+        Ok(result)
+    }
+
+    /// [`Self::outbox_max_age_hours`] as a duration, fails if it is too large to represent
+    pub fn outbox_max_age(&self) -> anyhow::Result<TimeDelta> {
+        i64::try_from(self.outbox_max_age_hours)
+            .ok()
+            .and_then(TimeDelta::try_hours)
+            .with_context(|| {
+                format!(
+                    "outbox_max_age_hours is too large: {}",
+                    self.outbox_max_age_hours
+                )
+            })
+    }
+
+    /// The behaviour from before channels could be configured, discord with email as a fallback
+    fn legacy() -> Self {
+        Self {
+            channels: vec![
+                ChannelConfig {
+                    kind: ChannelKind::LegacyDiscord,
+                    mode: ChannelMode::Failover,
+                    min_severity: None,
+                    retry: Default::default(),
+                },
+                ChannelConfig {
+                    kind: ChannelKind::LegacyEmail,
+                    mode: ChannelMode::Failover,
+                    min_severity: None,
+                    retry: Default::default(),
+                },
+            ],
+            outbox_max_age_hours: Self::default_outbox_max_age_hours(),
+        }
+    }
+
+    /// Channels that fail to build are still included but fail when used so that the other channels are unaffected
+    pub fn build_channels(&self, config_folder: &Path) -> Vec<Channel> {
+        self.channels
+            .iter()
+            .map(|x| x.build(config_folder))
+            .collect()
+    }
+}
+
+impl ChannelConfig {
+    fn build(&self, config_folder: &Path) -> Channel {
+        let notifier = self
+            .kind
+            .build(config_folder, self.retry.clone())
+            .unwrap_or_else(|e| -> Box<dyn Notifier> {
+                Box::new(FailedToBuild {
+                    name: self.kind.name().to_string(),
+                    error: format!("{e:?}"),
+                })
+            });
+        Channel {
+            mode: self.mode,
+            min_severity: self.min_severity,
+            notifier,
+        }
+    }
+}
+
+impl ChannelKind {
+    fn name(&self) -> &'static str {
+        match self {
+            ChannelKind::Discord(_) | ChannelKind::LegacyDiscord => "discord",
+            ChannelKind::Email(_) | ChannelKind::LegacyEmail => "email",
+            ChannelKind::Slack(_) => "slack",
+            ChannelKind::Ntfy(_) => "ntfy",
+            ChannelKind::Gotify(_) => "gotify",
+            ChannelKind::Webhook(_) => "webhook",
+        }
+    }
+
+    fn build(&self, config_folder: &Path, retry: RetryPolicy) -> anyhow::Result<Box<dyn Notifier>> {
+        Ok(match self {
+            ChannelKind::Discord(config) => Box::new(Discord::new(config, retry)),
+            ChannelKind::Email(config) => Box::new(Email::new(config, retry)?),
+            ChannelKind::Slack(config) => Box::new(Slack::new(config, retry)),
+            ChannelKind::Ntfy(config) => Box::new(Ntfy::new(config, retry)),
+            ChannelKind::Gotify(config) => Box::new(Gotify::new(config, retry)),
+            ChannelKind::Webhook(config) => Box::new(Webhook::new(config, retry)?),
+            ChannelKind::LegacyDiscord => Box::new(Discord::new(
+                &DiscordConfig::load_legacy(config_folder)?,
+                retry,
+            )),
+            ChannelKind::LegacyEmail => Box::new(Email::new(
+                &EmailConfig::load_legacy(config_folder)?,
+                retry,
+            )?),
+        })
+    }
+}
+
+/// Stands in for a channel that could not be built and reports why when used
+struct FailedToBuild {
+    name: String,
+    error: String,
+}
+
+impl Notifier for FailedToBuild {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn send(&self, _msg: &Message) -> anyhow::Result<()> {
+        bail!("failed to setup {} channel: {}", self.name, self.error)
+    }
+}
@@ -1,66 +1,328 @@
 use std::{fs, path::Path, time::Duration};
 
-use anyhow::{bail, Context};
-use serde_json::json;
+use anyhow::Context;
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value};
 
-pub struct Discord {
-    url: String,
-}
+use super::{
+    check_response, header_seconds, log_title, post_json_unchecked, truncate, Message, Notifier,
+    RetryPolicy,
+};
+use crate::{logs_summary_line, LogInfo, Severity};
 
-impl Discord {
-    const RETRY_ATTEMPTS: u8 = 3;
-    const INTERVAL_BETWEEN_RETRY: Duration = Duration::from_secs(30);
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiscordConfig {
+    pub webhook_url: String,
+    /// Send an embed per log instead of plain text
+    #[serde(default)]
+    pub use_embeds: bool,
+}
 
-    pub fn new(config_folder: &Path) -> anyhow::Result<Self> {
+impl DiscordConfig {
+    /// Loads the url suffix from `d.data` in the config folder
+    pub fn load_legacy(config_folder: &Path) -> anyhow::Result<Self> {
         let filename = config_folder.join("d.data");
         let url_suffix = fs::read_to_string(&filename).with_context(|| {
             format!("failed to read discord webhook url suffix from {filename:?}")
         })?;
-        let url = format!("https://discord.com/api/webhooks/{url_suffix}");
-        Ok(Self { url })
+        let webhook_url = format!("https://discord.com/api/webhooks/{url_suffix}");
+        Ok(Self {
+            webhook_url,
+            use_embeds: false,
+        })
+    }
+}
+
+pub struct Discord {
+    url: String,
+    use_embeds: bool,
+    retry: RetryPolicy,
+}
+
+impl Discord {
+    /// Discord rejects messages with longer content than this
+    const MAX_CONTENT_LEN: usize = 2000;
+    /// Discord rejects messages with more embeds than this
+    const MAX_EMBEDS: usize = 10;
+    /// Discord rejects messages where the text in all the embeds combined is longer than this
+    const MAX_EMBEDS_TOTAL_LEN: usize = 6000;
+    const MAX_EMBED_TITLE_LEN: usize = 256;
+    const MAX_EMBED_DESCRIPTION_LEN: usize = 4096;
+
+    const COLOUR_RED: u32 = 0xE74C3C;
+    const COLOUR_ORANGE: u32 = 0xE67E22;
+    const COLOUR_YELLOW: u32 = 0xF1C40F;
+    const COLOUR_GREEN: u32 = 0x2ECC71;
+
+    pub fn new(config: &DiscordConfig, retry: RetryPolicy) -> Self {
+        Self {
+            url: config.webhook_url.clone(),
+            use_embeds: config.use_embeds,
+            retry,
+        }
+    }
+
+    /// The bodies to post for the message, split into as many as needed to stay within Discord's
+    /// limits. Logs are not split across messages unless a single log is too long on its own.
+    pub fn build_payloads(&self, msg: &Message) -> Vec<Value> {
+        if self.use_embeds {
+            build_embeds_payloads(msg)
+        } else {
+            build_content_payloads(msg)
+        }
+    }
+}
+
+impl Notifier for Discord {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    /// Posts each part in order, stopping at the first part that cannot be sent
+    ///
+    /// The message as a whole fails if any part fails, so the parts that were already posted are
+    /// posted again if it is retried later (eg. from the outbox). The error says which parts
+    /// those were.
+    fn send(&self, msg: &Message) -> anyhow::Result<()> {
+        let payloads = self.build_payloads(msg);
+        let part_failed = |i: usize| {
+            let delivered = match i {
+                0 => "no parts".to_string(),
+                1 => "part #1".to_string(),
+                _ => format!("parts #1 to #{i}"),
+            };
+            format!(
+                "failed to send part #{} of {} of message ({delivered} already delivered)",
+                i + 1,
+                payloads.len()
+            )
+        };
+        let mut wait_before_next = None;
+        for (i, body) in payloads.iter().enumerate() {
+            if let Some(wait) = wait_before_next.take() {
+                self.retry
+                    .check_requested_wait(self.name(), wait)
+                    .with_context(|| part_failed(i))?;
+                std::thread::sleep(wait);
+            }
+            self.retry
+                .run(self.name(), || {
+                    let resp = post_json_unchecked(&self.url, body)?;
+                    wait_before_next = rate_limit_exhausted_wait(resp.headers());
+                    check_response(resp)
+                })
+                .with_context(|| part_failed(i))?;
+        }
+        Ok(())
+    }
+}
+
+/// How long to wait before the next request if the response says no more requests are allowed
+/// until the rate limit resets
+fn rate_limit_exhausted_wait(headers: &HeaderMap) -> Option<Duration> {
+    let remaining = headers.get("X-RateLimit-Remaining")?.to_str().ok()?;
+    if remaining.trim() != "0" {
+        return None;
     }
+    header_seconds(headers, "X-RateLimit-Reset-After")
+}
+
+/// Each log is a section of the content (like [`crate::build_err_msg_from_logs`] without truncation)
+fn build_content_payloads(msg: &Message) -> Vec<Value> {
+    let mut sections = Vec::new();
+    if msg.log_infos.is_empty() {
+        sections.push(msg.text.clone());
+    } else {
+        let separator = "---\n";
+        for log_info in msg.log_infos.iter() {
+            sections.push(format!("{separator}{log_info}"));
+        }
+        sections.push(format!(
+            "{separator}{}\n{separator}",
+            logs_summary_line(&msg.log_infos)
+        ));
+        sections.extend(msg.notes.iter().map(|x| format!("{x}\n")));
+    }
+    pack_sections(sections, Discord::MAX_CONTENT_LEN)
+        .into_iter()
+        .map(|content| json!({ "content": content }))
+        .collect()
+}
 
-    pub fn send(msg: &str, config_folder: &Path) -> anyhow::Result<()> {
-        let discord = Self::new(config_folder)?;
-
-        for i in 0..Self::RETRY_ATTEMPTS {
-            // Wait before trying again
-            if i > 0 {
-                eprintln!(
-                    "Going to sleep for {} seconds before retrying discord",
-                    Self::INTERVAL_BETWEEN_RETRY.as_secs()
-                );
-                std::thread::sleep(Self::INTERVAL_BETWEEN_RETRY);
+/// Combines the sections into as few chunks as possible that are each at most `max_len`
+/// characters. Sections that are too long on their own are split on line boundaries (and lines
+/// that are too long are truncated).
+fn pack_sections(sections: Vec<String>, max_len: usize) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    let mut push = |piece: String| {
+        let piece_len = piece.chars().count();
+        if current_len + piece_len > max_len && !current.is_empty() {
+            result.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+        current.push_str(&piece);
+        current_len += piece_len;
+    };
+    for section in sections {
+        if section.chars().count() <= max_len {
+            push(section);
+        } else {
+            for line in section.split_inclusive('\n') {
+                push(truncate(line, max_len));
             }
+        }
+    }
+    if !current.is_empty() {
+        result.push(current);
+    }
+    result
+}
 
-            match send_blocking_reqwest(msg, &discord.url) {
-                Ok(()) => return Ok(()),
-                Err(e) => eprintln!(
-                    "attempt #{} failed to send via discord with msg: {e:?}",
-                    i + 1
-                ),
+fn build_embeds_payloads(msg: &Message) -> Vec<Value> {
+    let mut embeds: Vec<Value> = msg.log_infos.iter().flat_map(log_embeds).collect();
+    if msg.log_infos.is_empty() {
+        embeds.extend(text_embeds(
+            &format!("FS Log Monitor - {}", msg.severity),
+            &msg.text,
+            severity_colour(msg.severity),
+        ));
+    } else if !msg.notes.is_empty() {
+        embeds.extend(text_embeds(
+            "Notes",
+            &msg.notes.join("\n"),
+            severity_colour(msg.severity),
+        ));
+    }
+
+    let mut chunks: Vec<Vec<Value>> = Vec::new();
+    let mut chunk_len = 0;
+    for embed in embeds {
+        let len = embed_len(&embed);
+        match chunks.last_mut() {
+            Some(chunk)
+                if chunk.len() < Discord::MAX_EMBEDS
+                    && chunk_len + len <= Discord::MAX_EMBEDS_TOTAL_LEN =>
+            {
+                chunk.push(embed);
+                chunk_len += len;
+            }
+            _ => {
+                chunks.push(vec![embed]);
+                chunk_len = len;
             }
         }
-        bail!(
-            "failed to send via discord after {} attempts",
-            Self::RETRY_ATTEMPTS
-        )
     }
+
+    let content = if msg.log_infos.is_empty() {
+        String::new()
+    } else {
+        logs_summary_line(&msg.log_infos)
+    };
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            // Only include the summary once
+            let content = if i == 0 { content.as_str() } else { "" };
+            json!({ "content": content, "embeds": chunk })
+        })
+        .collect()
+}
+
+/// Number of characters in the embed that count towards Discord's limit for all the embeds in a message
+fn embed_len(embed: &Value) -> usize {
+    let text_len = |value: &Value| value.as_str().map_or(0, |x| x.chars().count());
+    let fields_len: usize = embed["fields"].as_array().map_or(0, |fields| {
+        fields
+            .iter()
+            .map(|x| text_len(&x["name"]) + text_len(&x["value"]))
+            .sum()
+    });
+    text_len(&embed["title"]) + text_len(&embed["description"]) + fields_len
+}
+
+/// Splits the description on line boundaries into parts that each fit in an embed
+fn split_description(description: &str) -> Vec<String> {
+    let lines = description
+        .split_inclusive('\n')
+        .map(|x| x.to_string())
+        .collect();
+    let mut result = pack_sections(lines, Discord::MAX_EMBED_DESCRIPTION_LEN);
+    if result.is_empty() {
+        result.push(String::new());
+    }
+    result
 }
 
-fn send_blocking_reqwest(msg: &str, url: &str) -> anyhow::Result<()> {
-    let resp = reqwest::blocking::Client::new()
-        .request(reqwest::Method::POST, url)
-        .header("Content-Type", "application/json")
-        .body(json!({ "content": msg }).to_string())
-        .send()?;
-    if resp.status().is_success() {
-        Ok(())
+/// Embeds after the first for a description that does not fit in one
+fn continuation_embed(title: &str, description: String, colour: u32) -> Value {
+    json!({
+        "title": truncate(&format!("{title} (continued)"), Discord::MAX_EMBED_TITLE_LEN),
+        "description": description,
+        "color": colour,
+    })
+}
+
+/// One embed for the log unless its description is too long, then it is continued in more embeds
+fn log_embeds(log_info: &LogInfo) -> Vec<Value> {
+    let title = log_title(log_info);
+    let mut description = String::new();
+    if let Some(summary) = &log_info.summary {
+        description.push_str(&summary.to_string());
+        description.push('\n');
+    }
+    for entry in log_info.errors_and_warnings.iter() {
+        description.push_str(&entry.to_string());
+        description.push('\n');
+    }
+
+    let colour = match log_info.abnormal_outcome.as_deref() {
+        Some("[Error]") => Discord::COLOUR_RED,
+        Some("[Stopped]") => Discord::COLOUR_ORANGE,
+        Some("[Warning]") => Discord::COLOUR_YELLOW,
+        _ => severity_colour(log_info.severity()),
+    };
+    let profile = if log_info.profile.is_empty() {
+        "-"
     } else {
-        let unexpected_response_value =
-            resp.error_for_status().context("error response returned")?;
+        &log_info.profile
+    };
+    let mut parts = split_description(&description).into_iter();
+    let first = json!({
+        "title": truncate(&title, Discord::MAX_EMBED_TITLE_LEN),
+        "description": parts.next().unwrap_or_default(),
+        "color": colour,
+        "fields": [
+            { "name": "Time", "value": log_info.date_time.format("%F %H:%M:%S").to_string(), "inline": true },
+            { "name": "Errors", "value": log_info.error_count().to_string(), "inline": true },
+            { "name": "Profile", "value": profile, "inline": true },
+        ],
+    });
+    std::iter::once(first)
+        .chain(parts.map(|part| continuation_embed(&title, part, colour)))
+        .collect()
+}
+
+fn text_embeds(title: &str, description: &str, colour: u32) -> Vec<Value> {
+    let mut parts = split_description(description).into_iter();
+    let first = json!({
+        "title": truncate(title, Discord::MAX_EMBED_TITLE_LEN),
+        "description": parts.next().unwrap_or_default(),
+        "color": colour,
+    });
+    std::iter::once(first)
+        .chain(parts.map(|part| continuation_embed(title, part, colour)))
+        .collect()
+}
 
-        // Assumption the code above will always result in an error being returned
-        bail!("not a successful status but conversion to error still failed? Response: {unexpected_response_value:?}")
+/// Green is used for info as the only messages without logs at that level are alive messages
+fn severity_colour(severity: Severity) -> u32 {
+    match severity {
+        Severity::Info => Discord::COLOUR_GREEN,
+        Severity::Warning => Discord::COLOUR_YELLOW,
+        Severity::Error => Discord::COLOUR_RED,
     }
 }
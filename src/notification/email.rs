@@ -1,86 +1,443 @@
+mod html;
+
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::Context;
-use lettre::message::Mailbox;
-use lettre::transport::smtp::authentication::Credentials;
-use lettre::{Message, SmtpTransport, Transport};
+use anyhow::{bail, Context};
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, Mailboxes, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::{FileTransport, SendmailTransport, SmtpTransport, Transport as _};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+use super::{Message, Notifier, RetryHint, RetryPolicy};
+use crate::Severity;
+
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
-struct EmailConfig {
+pub struct EmailConfig {
+    pub from_name: String,
+    /// Password used to log in to the SMTP server (not needed if `auth` is [`SmtpAuth::None`])
+    #[serde(default)]
+    pub pass: String,
+    pub from_email: String,
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    #[serde(default)]
+    pub bcc: Vec<String>,
+    /// Attach the original log files of the logs in the message
+    #[serde(default)]
+    pub attach_logs: bool,
+    /// Logs are not attached once their total size would exceed this many bytes
+    #[serde(default = "EmailConfig::default_max_attachments_size")]
+    pub max_attachments_size: u64,
+    /// Recipients for specific messages, the first route that matches the message is used instead
+    /// of `to`, `cc` and `bcc`
+    #[serde(default)]
+    pub routes: Vec<EmailRoute>,
+    #[serde(default = "EmailConfig::default_subject")]
+    pub subject: String,
+    /// How the email is handed over for delivery, the SMTP settings below are only used by [`EmailTransport::Smtp`]
+    #[serde(default)]
+    pub transport: EmailTransport,
+    /// Required for [`EmailTransport::Smtp`]
+    #[serde(default)]
+    pub smtp_host: Option<String>,
+    /// If not set the standard port for the TLS mode is used
+    #[serde(default)]
+    pub smtp_port: Option<u16>,
+    #[serde(default)]
+    pub tls: TlsMode,
+    #[serde(default)]
+    pub auth: SmtpAuth,
+    /// Username to log in with if different from the from email address
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+/// Sends matching messages to a different set of recipients
+///
+/// For example to only send stopped syncs to the admin:
+/// ```ron
+/// (outcomes: ["[Stopped]"], to: ["admin@example.com"])
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailRoute {
+    /// If not empty the route only matches messages where every log has one of these outcomes
+    #[serde(default)]
+    pub outcomes: Vec<String>,
+    /// If set the route only matches messages that are at least this severe
+    #[serde(default)]
+    pub min_severity: Option<Severity>,
+    #[serde(default)]
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    #[serde(default)]
+    pub bcc: Vec<String>,
+}
+
+impl EmailRoute {
+    fn is_match(&self, msg: &Message) -> bool {
+        let outcomes_match = self.outcomes.is_empty()
+            || (!msg.log_infos.is_empty()
+                && msg.log_infos.iter().all(|log_info| {
+                    log_info
+                        .abnormal_outcome
+                        .as_ref()
+                        .is_some_and(|outcome| self.outcomes.contains(outcome))
+                }));
+        let severity_match = self.min_severity.is_none_or(|x| msg.severity >= x);
+        outcomes_match && severity_match
+    }
+}
+
+/// How the email is handed over for delivery
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum EmailTransport {
+    /// Connect to the SMTP server in the config
+    #[default]
+    Smtp,
+    /// Pass the email to a local MTA using the sendmail command
+    Sendmail {
+        /// Defaults to `sendmail` found using PATH
+        #[serde(default)]
+        command: Option<String>,
+    },
+    /// Write each email as a `.eml` file in the folder instead of sending it
+    Spool { dir: PathBuf },
+}
+
+/// How the connection to the SMTP server is encrypted
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TlsMode {
+    /// TLS from the start of the connection (default port 465)
+    #[default]
+    Implicit,
+    /// Upgrade to TLS using STARTTLS, fails if the server does not support it (default port 587)
+    StartTls,
+    /// No encryption, only intended for relays on a trusted network (default port 25)
+    None,
+}
+
+/// Authentication used with the SMTP server
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpAuth {
+    /// Use whichever of PLAIN or LOGIN the server supports
+    #[default]
+    Automatic,
+    Plain,
+    Login,
+    Xoauth2,
+    /// Do not log in (eg. a local relay that accepts mail without authentication)
+    None,
+}
+
+/// Format of `e.data` from before the email settings were part of the notification config
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LegacyEmailConfig {
     from_name: String,
     pass: String,
-    #[serde(default = "EmailConfig::default_from_email")]
+    #[serde(default = "LegacyEmailConfig::default_from_email")]
     from_email: String,
-    #[serde(default = "EmailConfig::default_to_email")]
+    /// Deprecated in favour of [`EmailConfig::to`], one or more addresses separated by commas
+    #[serde(default = "LegacyEmailConfig::default_to_email")]
     to_email: String,
     #[serde(default = "EmailConfig::default_subject")]
     subject: String,
 }
 
-impl EmailConfig {
+impl LegacyEmailConfig {
     fn default_from_email() -> String {
         "wykies.notices@gmail.com".to_string()
     }
     fn default_to_email() -> String {
         "it@wykies.com".to_string()
     }
+}
+
+impl From<LegacyEmailConfig> for EmailConfig {
+    fn from(value: LegacyEmailConfig) -> Self {
+        let LegacyEmailConfig {
+            from_name,
+            pass,
+            from_email,
+            to_email,
+            subject,
+        } = value;
+        Self {
+            from_name,
+            pass,
+            from_email,
+            to: vec![to_email],
+            cc: Default::default(),
+            bcc: Default::default(),
+            attach_logs: Default::default(),
+            max_attachments_size: Self::default_max_attachments_size(),
+            routes: Default::default(),
+            subject,
+            transport: Default::default(),
+            smtp_host: Some("smtp.gmail.com".to_string()),
+            smtp_port: Default::default(),
+            tls: Default::default(),
+            auth: Default::default(),
+            username: Default::default(),
+        }
+    }
+}
+
+impl EmailConfig {
     fn default_subject() -> String {
         "Notification from connection monitor".to_string()
     }
+    fn default_max_attachments_size() -> u64 {
+        10 * 1024 * 1024
+    }
+
+    /// Loads the JSON settings from `e.data` in the config folder
+    pub fn load_legacy(config_folder: &Path) -> anyhow::Result<Self> {
+        let filename = config_folder.join("e.data");
+        let file_contents = fs::read_to_string(&filename)
+            .with_context(|| format!("failed to read email settings from {filename:?}"))?;
+        let legacy_config: LegacyEmailConfig = serde_json::from_str(&file_contents)
+            .with_context(|| format!("failed to parse contents of {filename:?} as email config"))?;
+        Ok(legacy_config.into())
+    }
 }
 
 pub struct Email {
     from_mailbox: Mailbox,
-    to_mailbox: Mailbox,
+    default_recipients: Recipients,
+    routes: Vec<(EmailRoute, Recipients)>,
     subject: String,
-    transport: SmtpTransport,
+    attach_logs: bool,
+    max_attachments_size: u64,
+    transport: Transport,
+    retry: RetryPolicy,
+}
+
+/// The built version of [`EmailTransport`]
+enum Transport {
+    Smtp(SmtpTransport),
+    Sendmail(SendmailTransport),
+    Spool(FileTransport),
+}
+
+#[derive(Debug)]
+struct Recipients {
+    to: Vec<Mailbox>,
+    cc: Vec<Mailbox>,
+    bcc: Vec<Mailbox>,
+}
+
+impl Recipients {
+    fn new(to: &[String], cc: &[String], bcc: &[String]) -> anyhow::Result<Self> {
+        let result = Self {
+            to: parse_mailboxes(to).context("failed to parse to email address")?,
+            cc: parse_mailboxes(cc).context("failed to parse cc email address")?,
+            bcc: parse_mailboxes(bcc).context("failed to parse bcc email address")?,
+        };
+        if result.to.is_empty() && result.cc.is_empty() && result.bcc.is_empty() {
+            bail!("no recipients set");
+        }
+        Ok(result)
+    }
 }
+
+/// Each entry may contain multiple addresses separated by commas
+fn parse_mailboxes(addresses: &[String]) -> anyhow::Result<Vec<Mailbox>> {
+    let mut result = Vec::new();
+    for address in addresses {
+        let mailboxes: Mailboxes = address
+            .parse()
+            .with_context(|| format!("invalid address: {address:?}"))?;
+        result.extend(mailboxes);
+    }
+    Ok(result)
+}
+
 impl Email {
-    pub fn new(config_folder: &Path) -> anyhow::Result<Self> {
-        let filename = config_folder.join("e.data");
-        let file_contents = fs::read_to_string(&filename)
-            .with_context(|| format!("failed to read email settings from {filename:?}"))?;
-        let email_config: EmailConfig = serde_json::from_str(&file_contents)
-            .with_context(|| format!("failed to parse contents of {filename:?} as email config"))?;
+    pub fn new(email_config: &EmailConfig, retry: RetryPolicy) -> anyhow::Result<Self> {
         let from_mailbox = Mailbox {
-            name: Some(email_config.from_name),
+            name: Some(email_config.from_name.clone()),
             email: email_config
                 .from_email
                 .parse()
                 .context("failed to parse from email address")?,
         };
-        let to_mailbox: Mailbox = email_config
-            .to_email
-            .parse()
-            .context("failed to parse to email address")?;
-        let transport = SmtpTransport::relay("smtp.gmail.com")
-            .context("failed to build SmtpTransport")?
-            .credentials(Credentials::new(email_config.from_email, email_config.pass))
-            .build();
-        let subject = email_config.subject;
+        let default_recipients =
+            Recipients::new(&email_config.to, &email_config.cc, &email_config.bcc)?;
+        let mut routes = Vec::new();
+        for (i, route) in email_config.routes.iter().enumerate() {
+            let recipients = Recipients::new(&route.to, &route.cc, &route.bcc)
+                .with_context(|| format!("invalid recipients for email route #{}", i + 1))?;
+            routes.push((route.clone(), recipients));
+        }
+        let transport = build_transport(email_config)?;
+        let subject = email_config.subject.clone();
         Ok(Self {
             from_mailbox,
-            to_mailbox,
+            default_recipients,
+            routes,
             subject,
+            attach_logs: email_config.attach_logs,
+            max_attachments_size: email_config.max_attachments_size,
             transport,
+            retry,
+        })
+    }
+
+    fn recipients(&self, msg: &Message) -> &Recipients {
+        self.routes
+            .iter()
+            .find(|(route, _)| route.is_match(msg))
+            .map(|(_, recipients)| recipients)
+            .unwrap_or(&self.default_recipients)
+    }
+
+    /// Reads the log files to attach, returns notes for the ones that were left out
+    fn build_attachments(&self, msg: &Message) -> (Vec<SinglePart>, Vec<String>) {
+        let mut attachments = Vec::new();
+        let mut notes = Vec::new();
+        if !self.attach_logs {
+            return (attachments, notes);
+        }
+        let mut total_size = 0;
+        for path in msg.log_infos.iter().filter_map(|x| x.path.as_ref()) {
+            let file_name = path
+                .file_name()
+                .map(|x| x.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let contents = match fs::read(path) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    notes.push(format!("Failed to read {file_name} to attach: {e}"));
+                    continue;
+                }
+            };
+            total_size += contents.len() as u64;
+            if total_size > self.max_attachments_size {
+                notes.push(format!(
+                    "{file_name} not attached as the attachments would exceed {} bytes",
+                    self.max_attachments_size
+                ));
+                total_size -= contents.len() as u64;
+                continue;
+            }
+            let content_type = if file_name.ends_with(".html") {
+                ContentType::TEXT_HTML
+            } else if file_name.ends_with(".log") {
+                ContentType::TEXT_PLAIN
+            } else {
+                ContentType::parse("application/octet-stream").expect("valid content type")
+            };
+            attachments.push(Attachment::new(file_name).body(contents, content_type));
+        }
+        (attachments, notes)
+    }
+}
+
+impl Notifier for Email {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn send(&self, msg: &Message) -> anyhow::Result<()> {
+        let recipients = self.recipients(msg);
+        let mut builder = lettre::Message::builder()
+            .from(self.from_mailbox.clone())
+            .subject(self.subject.clone());
+        for mailbox in recipients.to.iter() {
+            builder = builder.to(mailbox.clone());
+        }
+        for mailbox in recipients.cc.iter() {
+            builder = builder.cc(mailbox.clone());
+        }
+        for mailbox in recipients.bcc.iter() {
+            builder = builder.bcc(mailbox.clone());
+        }
+        let (attachments, attachment_notes) = self.build_attachments(msg);
+        let mut text = msg.text.clone();
+        for note in attachment_notes.iter() {
+            text.push_str(note);
+            text.push('\n');
+        }
+        let html = html::build_html(msg, &attachment_notes);
+        let mut body = MultiPart::alternative_plain_html(text, html);
+        if !attachments.is_empty() {
+            body = attachments
+                .into_iter()
+                .fold(MultiPart::mixed().multipart(body), |acc, x| {
+                    acc.singlepart(x)
+                });
+        }
+        let email_msg = builder.multipart(body)?;
+        self.retry.run(self.name(), || match &self.transport {
+            Transport::Smtp(transport) => match transport.send(&email_msg) {
+                Ok(_) => Ok(()),
+                // Rejected by the server (eg. invalid recipient or credentials)
+                Err(e) if e.is_permanent() => Err(anyhow::Error::new(e)
+                    .context(RetryHint::DoNotRetry)
+                    .context("failed to send email")),
+                Err(e) => Err(anyhow::Error::new(e).context("failed to send email")),
+            },
+            Transport::Sendmail(transport) => transport
+                .send(&email_msg)
+                .context("failed to pass email to sendmail"),
+            Transport::Spool(transport) => transport
+                .send(&email_msg)
+                .map(|_| ())
+                .context("failed to write email to spool folder"),
         })
     }
+}
 
-    pub fn send(msg: &str, config_folder: &Path) -> anyhow::Result<()> {
-        let email = Self::new(config_folder)?;
+fn build_transport(email_config: &EmailConfig) -> anyhow::Result<Transport> {
+    Ok(match &email_config.transport {
+        EmailTransport::Smtp => Transport::Smtp(build_smtp_transport(email_config)?),
+        EmailTransport::Sendmail { command } => Transport::Sendmail(match command {
+            Some(command) => SendmailTransport::new_with_command(command),
+            None => SendmailTransport::new(),
+        }),
+        EmailTransport::Spool { dir } => {
+            if !dir.is_dir() {
+                bail!("email spool folder does not exist: {dir:?}");
+            }
+            Transport::Spool(FileTransport::new(dir))
+        }
+    })
+}
 
-        let email_msg = Message::builder()
-            .from(email.from_mailbox.clone())
-            .to(email.to_mailbox.clone())
-            .subject(email.subject.clone())
-            .body(msg.to_string())?;
-        email
-            .transport
-            .send(&email_msg)
-            .context("failed to send email")?;
-        Ok(())
+fn build_smtp_transport(email_config: &EmailConfig) -> anyhow::Result<SmtpTransport> {
+    let Some(host) = &email_config.smtp_host else {
+        bail!("smtp_host must be set to send email using SMTP");
+    };
+    let mut builder = match email_config.tls {
+        TlsMode::Implicit => SmtpTransport::relay(host),
+        TlsMode::StartTls => SmtpTransport::starttls_relay(host),
+        TlsMode::None => Ok(SmtpTransport::builder_dangerous(host)),
     }
+    .with_context(|| format!("failed to build SmtpTransport for {host:?}"))?;
+    if let Some(port) = email_config.smtp_port {
+        builder = builder.port(port);
+    }
+
+    let mechanisms = match email_config.auth {
+        SmtpAuth::None => return Ok(builder.build()),
+        SmtpAuth::Automatic => vec![Mechanism::Plain, Mechanism::Login],
+        SmtpAuth::Plain => vec![Mechanism::Plain],
+        SmtpAuth::Login => vec![Mechanism::Login],
+        SmtpAuth::Xoauth2 => vec![Mechanism::Xoauth2],
+    };
+    let username = email_config
+        .username
+        .clone()
+        .unwrap_or_else(|| email_config.from_email.clone());
+    Ok(builder
+        .credentials(Credentials::new(username, email_config.pass.clone()))
+        .authentication(mechanisms)
+        .build())
 }
@@ -0,0 +1,113 @@
+//! Builds the HTML version of the email body
+
+use std::fmt::Write as _;
+
+use crate::{logs_summary_line, notification::Message, LogInfo};
+
+/// Formats the message as a table of the logs followed by a table of the errors and warnings for
+/// each log and then the notes. Messages without logs or notes just show the text.
+pub(super) fn build_html(msg: &Message, extra_notes: &[String]) -> String {
+    let mut result = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>\n\
+         table { border-collapse: collapse; margin-bottom: 1em; }\n\
+         th, td { border: 1px solid #999; padding: 4px 8px; text-align: left; vertical-align: top; }\n\
+         th { background-color: #eee; }\n\
+         .Error { color: #b00020; }\n\
+         .Warning { color: #a05a00; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    let _ = writeln!(
+        result,
+        "<h2 class=\"{0}\">FS Log Monitor - {0}</h2>",
+        msg.severity
+    );
+
+    if msg.log_infos.is_empty() && msg.notes.is_empty() {
+        let _ = writeln!(result, "<pre>{}</pre>", escape(&msg.text));
+    } else {
+        if !msg.log_infos.is_empty() {
+            let _ = writeln!(
+                result,
+                "<p>{}</p>",
+                escape(&logs_summary_line(&msg.log_infos))
+            );
+            push_logs_table(&mut result, &msg.log_infos);
+            for log_info in msg.log_infos.iter() {
+                push_entries_table(&mut result, log_info);
+            }
+        }
+        push_list(&mut result, &msg.notes);
+    }
+    push_list(&mut result, extra_notes);
+
+    result.push_str("</body>\n</html>\n");
+    result
+}
+
+fn push_logs_table(result: &mut String, log_infos: &[LogInfo]) {
+    result.push_str(
+        "<table>\n<tr><th>Date</th><th>Source</th><th>Profile</th><th>Outcome</th>\
+         <th>Errors</th><th>Warnings</th><th>Summary</th></tr>\n",
+    );
+    for log_info in log_infos {
+        let _ = writeln!(
+            result,
+            "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            log_info.severity(),
+            log_info.date_time.format("%F %H:%M:%S"),
+            escape(&log_info.source),
+            escape(&log_info.profile),
+            escape(log_info.abnormal_outcome.as_deref().unwrap_or("[ - ]")),
+            log_info.error_count(),
+            log_info.warning_count(),
+            log_info
+                .summary
+                .as_ref()
+                .map(|x| escape(&x.to_string()))
+                .unwrap_or_default(),
+        );
+    }
+    result.push_str("</table>\n");
+}
+
+fn push_entries_table(result: &mut String, log_info: &LogInfo) {
+    if log_info.errors_and_warnings.is_empty() {
+        return;
+    }
+    let _ = writeln!(
+        result,
+        "<h3>{} {} {}</h3>",
+        escape(&log_info.source),
+        escape(&log_info.profile),
+        log_info.date_time.format("%F %H:%M:%S")
+    );
+    result.push_str("<table>\n<tr><th>Time</th><th>Severity</th><th>Message</th></tr>\n");
+    for entry in log_info.errors_and_warnings.iter() {
+        let _ = writeln!(
+            result,
+            "<tr class=\"{0}\"><td>{1}</td><td>{0}</td><td>{2}</td></tr>",
+            entry.severity,
+            entry.time.format("%H:%M:%S"),
+            escape(&entry.message),
+        );
+    }
+    result.push_str("</table>\n");
+}
+
+fn push_list(result: &mut String, items: &[String]) {
+    if items.is_empty() {
+        return;
+    }
+    result.push_str("<ul>\n");
+    for item in items {
+        let _ = writeln!(result, "<li>{}</li>", escape(item));
+    }
+    result.push_str("</ul>\n");
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
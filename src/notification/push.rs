@@ -0,0 +1,197 @@
+use std::collections::BTreeSet;
+
+use serde_json::json;
+
+use super::{check_response, log_title, Message, Notifier, RetryPolicy};
+use crate::Severity;
+
+/// Settings for publishing to a topic on an ntfy server
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NtfyConfig {
+    /// Base url of the server (eg. https://ntfy.sh)
+    pub server_url: String,
+    pub topic: String,
+    /// Access token for servers that require authentication
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Settings for sending to a Gotify server
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GotifyConfig {
+    /// Base url of the server (eg. https://gotify.example.com)
+    pub server_url: String,
+    /// Token of the application to send as
+    pub app_token: String,
+}
+
+/// How urgently a push notification should get the attention of the receiver
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Priority {
+    Low,
+    Default,
+    High,
+}
+
+impl Priority {
+    /// Logs with an "[Error]" outcome are high and other outcomes (eg. "[Stopped]") are default.
+    /// Messages that are not about logs (eg. alive messages) use the severity of the message.
+    fn from_msg(msg: &Message) -> Self {
+        let from_severity = |severity| match severity {
+            Severity::Info => Priority::Low,
+            Severity::Warning => Priority::Default,
+            Severity::Error => Priority::High,
+        };
+        if msg.log_infos.is_empty() {
+            return from_severity(msg.severity);
+        }
+        let from_logs = msg
+            .log_infos
+            .iter()
+            .map(|x| match x.abnormal_outcome.as_deref() {
+                Some("[Error]") => Priority::High,
+                Some(_) => Priority::Default,
+                None => from_severity(x.severity()),
+            })
+            .max()
+            .unwrap_or(Priority::Low);
+        if msg.notes.is_empty() {
+            from_logs
+        } else {
+            from_logs.max(Priority::Default)
+        }
+    }
+
+    fn ntfy_value(&self) -> u8 {
+        match self {
+            Priority::Low => 2,
+            Priority::Default => 3,
+            Priority::High => 4,
+        }
+    }
+
+    fn gotify_value(&self) -> u8 {
+        match self {
+            Priority::Low => 2,
+            Priority::Default => 5,
+            Priority::High => 8,
+        }
+    }
+}
+
+/// Title for the notification, names the log if there is only one
+fn push_title(msg: &Message) -> String {
+    match msg.log_infos.as_slice() {
+        [] => format!("FS Log Monitor - {}", msg.severity),
+        [log_info] => log_title(log_info),
+        log_infos => format!(
+            "FS Log Monitor - {} logs ({})",
+            log_infos.len(),
+            msg.severity
+        ),
+    }
+}
+
+/// The outcomes (without brackets) and profiles of the logs in the message
+fn push_tags(msg: &Message) -> Vec<String> {
+    let outcomes = msg.log_infos.iter().filter_map(|x| {
+        x.abnormal_outcome
+            .as_deref()
+            .map(|outcome| outcome.trim_matches(['[', ']']).to_lowercase())
+    });
+    let profiles = msg
+        .log_infos
+        .iter()
+        .filter(|x| !x.profile.is_empty())
+        .map(|x| x.profile.clone());
+    let result: BTreeSet<String> = outcomes.chain(profiles).collect();
+    result.into_iter().collect()
+}
+
+pub struct Ntfy {
+    server_url: String,
+    topic: String,
+    token: Option<String>,
+    retry: RetryPolicy,
+}
+
+impl Ntfy {
+    pub fn new(config: &NtfyConfig, retry: RetryPolicy) -> Self {
+        Self {
+            server_url: config.server_url.trim_end_matches('/').to_string(),
+            topic: config.topic.clone(),
+            token: config.token.clone(),
+            retry,
+        }
+    }
+}
+
+impl Notifier for Ntfy {
+    fn name(&self) -> &str {
+        "ntfy"
+    }
+
+    fn send(&self, msg: &Message) -> anyhow::Result<()> {
+        // Uses JSON publishing as headers do not allow non-ASCII titles
+        let body = json!({
+            "topic": self.topic,
+            "title": push_title(msg),
+            "message": msg.text,
+            "priority": Priority::from_msg(msg).ntfy_value(),
+            "tags": push_tags(msg),
+        })
+        .to_string();
+        self.retry.run(self.name(), || {
+            let mut request = reqwest::blocking::Client::new()
+                .post(&self.server_url)
+                .header("Content-Type", "application/json")
+                .body(body.clone());
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            check_response(request.send()?)
+        })
+    }
+}
+
+pub struct Gotify {
+    url: String,
+    app_token: String,
+    retry: RetryPolicy,
+}
+
+impl Gotify {
+    pub fn new(config: &GotifyConfig, retry: RetryPolicy) -> Self {
+        Self {
+            url: format!("{}/message", config.server_url.trim_end_matches('/')),
+            app_token: config.app_token.clone(),
+            retry,
+        }
+    }
+}
+
+impl Notifier for Gotify {
+    fn name(&self) -> &str {
+        "gotify"
+    }
+
+    fn send(&self, msg: &Message) -> anyhow::Result<()> {
+        let body = json!({
+            "title": push_title(msg),
+            "message": msg.text,
+            "priority": Priority::from_msg(msg).gotify_value(),
+        })
+        .to_string();
+        self.retry.run(self.name(), || {
+            let resp = reqwest::blocking::Client::new()
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .header("X-Gotify-Key", &self.app_token)
+                .body(body.clone())
+                .send()?;
+            check_response(resp)
+        })
+    }
+}
@@ -0,0 +1,214 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, ensure, Context as _};
+
+/// How failed attempts to send are retried, set per channel
+///
+/// The defaults match the original behaviour of 3 attempts 30 seconds apart
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Includes the first attempt
+    #[serde(default = "RetryPolicy::default_max_attempts")]
+    pub max_attempts: u8,
+    /// Time to wait before the first retry
+    #[serde(default = "RetryPolicy::default_initial_delay_secs")]
+    pub initial_delay_secs: f64,
+    /// Each wait is this many times longer than the previous one
+    #[serde(default = "RetryPolicy::default_backoff_factor")]
+    pub backoff_factor: f64,
+    /// Fraction of each wait (0 to 1) that is randomly added or removed
+    #[serde(default)]
+    pub jitter: f64,
+    /// No more attempts are started once this much time would be exceeded by waiting for them
+    #[serde(default)]
+    pub max_total_secs: Option<f64>,
+    /// Longest wait the server can ask for (eg. using `Retry-After`) before giving up so that the
+    /// next channel is tried instead
+    #[serde(default = "RetryPolicy::default_max_retry_after_secs")]
+    pub max_retry_after_secs: f64,
+}
+
+/// Attached as context to errors from sending to change how they are retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RetryHint {
+    /// Retrying will not help (eg. the webhook was deleted)
+    DoNotRetry,
+    /// The server asked for the request to be retried after this long
+    RetryAfter(Duration),
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: Self::default_max_attempts(),
+            initial_delay_secs: Self::default_initial_delay_secs(),
+            backoff_factor: Self::default_backoff_factor(),
+            jitter: Default::default(),
+            max_total_secs: Default::default(),
+            max_retry_after_secs: Self::default_max_retry_after_secs(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retrying is given up if it would mean waiting longer than this, so that a run cannot hang
+    pub const MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+    fn default_max_attempts() -> u8 {
+        3
+    }
+    fn default_initial_delay_secs() -> f64 {
+        30.0
+    }
+    fn default_backoff_factor() -> f64 {
+        1.0
+    }
+    fn default_max_retry_after_secs() -> f64 {
+        60.0
+    }
+
+    /// Checks that the values can be used to calculate waits, called when the config is loaded
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(self.max_attempts >= 1, "max_attempts must be at least 1");
+        ensure!(
+            self.initial_delay_secs.is_finite() && self.initial_delay_secs >= 0.0,
+            "initial_delay_secs must be a non-negative number but is {}",
+            self.initial_delay_secs
+        );
+        ensure!(
+            self.backoff_factor.is_finite() && self.backoff_factor >= 1.0,
+            "backoff_factor must be a number of at least 1 but is {}",
+            self.backoff_factor
+        );
+        ensure!(
+            self.jitter.is_finite() && (0.0..=1.0).contains(&self.jitter),
+            "jitter must be between 0 and 1 but is {}",
+            self.jitter
+        );
+        ensure!(
+            self.max_retry_after_secs.is_finite() && self.max_retry_after_secs >= 0.0,
+            "max_retry_after_secs must be a non-negative number but is {}",
+            self.max_retry_after_secs
+        );
+        if let Some(max_total_secs) = self.max_total_secs {
+            ensure!(
+                max_total_secs.is_finite() && max_total_secs >= 0.0,
+                "max_total_secs must be a non-negative number but is {max_total_secs}"
+            );
+        }
+        Ok(())
+    }
+
+    /// The wait before the given retry (1 for the first retry) without jitter
+    ///
+    /// Waits too long to represent are [`Duration::MAX`] and invalid ones (eg. negative) are zero
+    pub fn delay_before_retry(&self, retry_number: u32) -> Duration {
+        let exponent = retry_number.saturating_sub(1) as i32;
+        let secs = self.initial_delay_secs * self.backoff_factor.powi(exponent);
+        secs_to_duration(secs)
+    }
+
+    /// Fails if the server asked to wait longer than [`Self::max_retry_after_secs`]
+    pub(super) fn check_requested_wait(&self, name: &str, wait: Duration) -> anyhow::Result<()> {
+        ensure!(
+            wait.as_secs_f64() <= self.max_retry_after_secs,
+            "not retrying {name} as the server asked to wait {:.3} seconds which is more than the maximum of {} seconds",
+            wait.as_secs_f64(),
+            self.max_retry_after_secs
+        );
+        Ok(())
+    }
+
+    fn with_jitter(&self, delay: Duration) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 || delay == Duration::MAX {
+            return delay;
+        }
+        // Scale by a random factor between 1 - jitter and 1 + jitter
+        secs_to_duration(delay.as_secs_f64() * (1.0 + jitter * (fastrand::f64() * 2.0 - 1.0)))
+    }
+
+    /// Calls `send` until it succeeds or the attempts or time run out, waiting between attempts
+    ///
+    /// Gives up instead of waiting longer than [`Self::MAX_WAIT`] before a retry
+    ///
+    /// If the error has a [`RetryHint`] it is followed instead of the policy's delay
+    pub fn run<F>(&self, name: &str, mut send: F) -> anyhow::Result<()>
+    where
+        F: FnMut() -> anyhow::Result<()>,
+    {
+        let start = Instant::now();
+        let max_total = self
+            .max_total_secs
+            .and_then(|x| Duration::try_from_secs_f64(x).ok());
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let error = match send() {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            let hint = error.downcast_ref::<RetryHint>().copied();
+            if hint == Some(RetryHint::DoNotRetry) {
+                return Err(error.context(format!("failed to send via {name}")));
+            }
+            eprintln!("attempt #{attempt} failed to send via {name} with msg: {error:?}");
+            if attempt >= u32::from(self.max_attempts) {
+                bail!("failed to send via {name} after {attempt} attempts");
+            }
+
+            let wait = match hint {
+                Some(RetryHint::RetryAfter(delay)) => {
+                    self.check_requested_wait(name, delay).with_context(|| {
+                        format!("failed to send via {name} after {attempt} attempts")
+                    })?;
+                    delay
+                }
+                _ => self.with_jitter(self.delay_before_retry(attempt)),
+            };
+            // A wait that would overflow is treated as exceeding the budget
+            let out_of_time = max_total.is_some_and(|max_total| {
+                start
+                    .elapsed()
+                    .checked_add(wait)
+                    .is_none_or(|end| end > max_total)
+            });
+            if out_of_time {
+                bail!("failed to send via {name} after {attempt} attempts, not enough time left to retry");
+            }
+            if wait > Self::MAX_WAIT {
+                bail!(
+                    "failed to send via {name} after {attempt} attempts, waiting {:.3} seconds to retry exceeds the maximum of {} seconds",
+                    wait.as_secs_f64(),
+                    Self::MAX_WAIT.as_secs()
+                );
+            }
+            eprintln!(
+                "Going to sleep for {:.3} seconds before retrying {name}",
+                wait.as_secs_f64()
+            );
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// Saturates at [`Duration::MAX`] for values that are too large, NaN and negative values are zero
+fn secs_to_duration(secs: f64) -> Duration {
+    if secs.is_nan() || secs <= 0.0 {
+        Duration::ZERO
+    } else {
+        Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+    }
+}
+
+impl std::fmt::Display for RetryHint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RetryHint::DoNotRetry => write!(f, "permanent failure, not retrying"),
+            RetryHint::RetryAfter(delay) => {
+                write!(f, "asked to retry after {:.3} seconds", delay.as_secs_f64())
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use serde_json::{json, Value};
+
+use super::{post_json, truncate, Message, Notifier, RetryPolicy};
+use crate::logs_summary_line;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlackConfig {
+    /// Incoming webhook url (eg. https://hooks.slack.com/services/...)
+    pub webhook_url: String,
+}
+
+/// Posts Block Kit formatted messages to a Slack incoming webhook
+pub struct Slack {
+    url: String,
+    retry: RetryPolicy,
+}
+
+impl Slack {
+    /// Slack rejects messages with more blocks than this
+    const MAX_BLOCKS: usize = 50;
+    const MAX_HEADER_LEN: usize = 150;
+    const MAX_SECTION_LEN: usize = 3000;
+
+    pub fn new(config: &SlackConfig, retry: RetryPolicy) -> Self {
+        Self {
+            url: config.webhook_url.clone(),
+            retry,
+        }
+    }
+
+    /// Builds the webhook payload with a header, the summary line, one section per log and then
+    /// the notes. If there are more blocks than Slack allows the last ones are replaced with a note
+    /// saying how many were left out.
+    pub fn build_payload(msg: &Message) -> Value {
+        let title = format!("FS Log Monitor - {}", msg.severity);
+        let mut sections = Vec::new();
+        if msg.log_infos.is_empty() && msg.notes.is_empty() {
+            sections.push(msg.text.clone());
+        } else {
+            if !msg.log_infos.is_empty() {
+                sections.push(logs_summary_line(&msg.log_infos));
+            }
+            sections.extend(msg.log_infos.iter().map(|x| x.to_string()));
+            sections.extend(msg.notes.iter().cloned());
+        }
+
+        // Leave space for the header
+        let max_sections = Self::MAX_BLOCKS - 1;
+        if sections.len() > max_sections {
+            let omitted_count = sections.len() - max_sections + 1;
+            sections.truncate(max_sections - 1);
+            sections.push(format!(
+                "*TRUNCATED* {omitted_count} more sections not shown"
+            ));
+        }
+
+        let mut blocks = vec![json!({
+            "type": "header",
+            "text": {
+                "type": "plain_text",
+                "text": truncate(&title, Self::MAX_HEADER_LEN),
+            },
+        })];
+        blocks.extend(sections.iter().map(|x| {
+            json!({
+                "type": "section",
+                "text": {
+                    "type": "mrkdwn",
+                    "text": truncate(&escape(x.trim_end()), Self::MAX_SECTION_LEN),
+                },
+            })
+        }));
+        json!({
+            // Used for the notification popup
+            "text": title,
+            "blocks": blocks,
+        })
+    }
+}
+
+impl Notifier for Slack {
+    fn name(&self) -> &str {
+        "slack"
+    }
+
+    fn send(&self, msg: &Message) -> anyhow::Result<()> {
+        let body = Self::build_payload(msg);
+        self.retry.run(self.name(), || post_json(&self.url, &body))
+    }
+}
+
+/// Escapes the characters that Slack uses for its control sequences
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
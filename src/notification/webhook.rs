@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use anyhow::Context;
+use regex::{Captures, Regex};
+use serde_json::Value;
+
+use super::{check_response, Message, Notifier, RetryPolicy};
+
+/// Settings for posting to any endpoint that accepts JSON (eg. Teams, Mattermost or Rocket.Chat)
+///
+/// The body template is JSON where the following placeholders are replaced in string values:
+/// - `{{message}}` the text of the message
+/// - `{{log_count}}` the number of logs the message is about
+/// - `{{severity}}` the severity of the message (Info, Warning or Error)
+///
+/// A string value that is exactly `{{log_count}}` is replaced by a number instead of a string.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default = "WebhookConfig::default_method")]
+    pub method: String,
+    /// Extra headers to send (Content-Type is always set to JSON)
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default = "WebhookConfig::default_body_template")]
+    pub body_template: String,
+}
+
+impl WebhookConfig {
+    fn default_method() -> String {
+        "POST".to_string()
+    }
+    fn default_body_template() -> String {
+        r#"{"text": "{{message}}"}"#.to_string()
+    }
+}
+
+pub struct Webhook {
+    url: String,
+    method: reqwest::Method,
+    headers: reqwest::header::HeaderMap,
+    body_template: Value,
+    retry: RetryPolicy,
+}
+
+impl Webhook {
+    const PLACEHOLDER_MESSAGE: &str = "{{message}}";
+    const PLACEHOLDER_LOG_COUNT: &str = "{{log_count}}";
+    const PLACEHOLDER_SEVERITY: &str = "{{severity}}";
+
+    pub fn new(config: &WebhookConfig, retry: RetryPolicy) -> anyhow::Result<Self> {
+        let method = config
+            .method
+            .parse()
+            .with_context(|| format!("invalid webhook method: {:?}", config.method))?;
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in config.headers.iter() {
+            let name: reqwest::header::HeaderName = name
+                .parse()
+                .with_context(|| format!("invalid webhook header name: {name:?}"))?;
+            let value = value
+                .parse()
+                .with_context(|| format!("invalid value for webhook header {name:?}"))?;
+            headers.insert(name, value);
+        }
+        let body_template = serde_json::from_str(&config.body_template)
+            .context("webhook body template is not valid JSON")?;
+        Ok(Self {
+            url: config.url.clone(),
+            method,
+            headers,
+            body_template,
+            retry,
+        })
+    }
+
+    /// Builds the body to send by filling in the placeholders in the template
+    pub fn build_body(&self, msg: &Message) -> Value {
+        let mut result = self.body_template.clone();
+        fill_placeholders(&mut result, msg);
+        result
+    }
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn send(&self, msg: &Message) -> anyhow::Result<()> {
+        let body = self.build_body(msg).to_string();
+        self.retry.run(self.name(), || {
+            let resp = reqwest::blocking::Client::new()
+                .request(self.method.clone(), &self.url)
+                .headers(self.headers.clone())
+                .header("Content-Type", "application/json")
+                .body(body.clone())
+                .send()?;
+            check_response(resp)
+        })
+    }
+}
+
+fn fill_placeholders(value: &mut Value, msg: &Message) {
+    match value {
+        Value::String(s) if s == Webhook::PLACEHOLDER_LOG_COUNT => {
+            *value = msg.log_infos.len().into();
+        }
+        Value::String(s) => {
+            *s = replace_placeholders(s, msg);
+        }
+        Value::Array(values) => values.iter_mut().for_each(|x| fill_placeholders(x, msg)),
+        Value::Object(map) => map.values_mut().for_each(|x| fill_placeholders(x, msg)),
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+/// Replaces all the placeholders in one pass so that placeholders in the values inserted (eg. in
+/// the text of the message) are left as is
+fn replace_placeholders(s: &str, msg: &Message) -> String {
+    static PLACEHOLDER_RE: OnceLock<Regex> = OnceLock::new();
+    let placeholder_re = PLACEHOLDER_RE.get_or_init(|| {
+        Regex::new(r"\{\{(message|log_count|severity)\}\}").expect("failed to compile regex")
+    });
+    placeholder_re
+        .replace_all(s, |caps: &Captures| match &caps[0] {
+            Webhook::PLACEHOLDER_MESSAGE => msg.text.clone(),
+            Webhook::PLACEHOLDER_LOG_COUNT => msg.log_infos.len().to_string(),
+            Webhook::PLACEHOLDER_SEVERITY => msg.severity.to_string(),
+            _ => unreachable!("regex only matches the placeholders"),
+        })
+        .into_owned()
+}
@@ -0,0 +1,139 @@
+use std::{
+    fs,
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Local, TimeDelta};
+
+use crate::notification::{send_to_channels, Channel, Message};
+
+/// Messages that could not be delivered, kept in a file next to the state file so that later runs
+/// can retry them
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Outbox {
+    /// Oldest first
+    messages: Vec<QueuedMessage>,
+    #[serde(skip)]
+    is_changed: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueuedMessage {
+    pub queued_at: DateTime<Local>,
+    pub msg: Message,
+}
+
+/// What happened to a message given to [`Outbox::send_or_queue`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Sent,
+    Queued,
+}
+
+impl Outbox {
+    /// The outbox file for a state file (eg. `state.outbox.ron` for `state.ron`)
+    pub fn path_for_state_file(state_file: &Path) -> PathBuf {
+        let stem = state_file
+            .file_stem()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+        state_file.with_file_name(format!("{stem}.outbox.ron"))
+    }
+
+    /// Returns an empty outbox if the file does not exist
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        if !path.as_ref().exists() {
+            return Ok(Self::default());
+        }
+        let s = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read file for Outbox: {:?}", path.as_ref()))?;
+        ron::from_str(&s).with_context(|| {
+            format!(
+                "failed to deserialize Outbox from contents of {:?}",
+                path.as_ref()
+            )
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        let s = ron::ser::to_string_pretty(&self, ron::ser::PrettyConfig::default())
+            .context("failed to convert to ron")?;
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("failed to open file to save Outbox: {:?}", path.as_ref()))?;
+        file.write_all(s.as_bytes()).with_context(|| {
+            format!(
+                "failed to write to file to save Outbox: {:?}",
+                path.as_ref()
+            )
+        })?;
+        self.is_changed = false;
+        Ok(())
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.is_changed
+    }
+
+    pub fn messages(&self) -> &[QueuedMessage] {
+        &self.messages
+    }
+
+    pub fn push(&mut self, msg: Message) {
+        self.messages.push(QueuedMessage {
+            queued_at: Local::now(),
+            msg,
+        });
+        self.is_changed = true;
+    }
+
+    /// Gives up on messages that have been queued for longer than `max_age` and returns them
+    pub fn remove_expired(&mut self, max_age: TimeDelta) -> Vec<QueuedMessage> {
+        let now = Local::now();
+        let (expired, kept) = std::mem::take(&mut self.messages)
+            .into_iter()
+            .partition(|x| now - x.queued_at > max_age);
+        self.messages = kept;
+        let expired: Vec<QueuedMessage> = expired;
+        if !expired.is_empty() {
+            self.is_changed = true;
+        }
+        expired
+    }
+
+    /// Sends the queued messages oldest first, stopping at the first one that cannot be delivered
+    /// so that they stay in order
+    pub fn flush(&mut self, channels: &[Channel]) {
+        while let Some(queued) = self.messages.first() {
+            if let Err(e) = send_to_channels(&queued.msg, channels) {
+                eprintln!(
+                    "failed to deliver message queued at {}: {e:?}",
+                    queued.queued_at.format("%F %T")
+                );
+                return;
+            }
+            self.messages.remove(0);
+            self.is_changed = true;
+        }
+    }
+
+    /// Sends the message unless older messages are still waiting (to keep them in order), queues
+    /// it if it is not sent
+    pub fn send_or_queue(&mut self, msg: Message, channels: &[Channel]) -> Delivery {
+        if self.messages.is_empty() {
+            match send_to_channels(&msg, channels) {
+                Ok(()) => return Delivery::Sent,
+                Err(e) => eprintln!("failed to send message, adding it to the outbox: {e:?}"),
+            }
+        }
+        self.push(msg);
+        Delivery::Queued
+    }
+}
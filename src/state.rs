@@ -1,26 +1,123 @@
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fs,
     io::Write as _,
     path::{Path, PathBuf},
 };
 
 use anyhow::Context;
-use chrono::{DateTime, Local, NaiveDateTime, NaiveTime};
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeDelta};
+use glob::Pattern;
+
+use crate::Severity;
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq)]
 #[serde(deny_unknown_fields)]
 pub struct AppState {
     last_alive_msg: DateTime<Local>,
     alive_msg_time: Option<NaiveTime>,
+    watched_folders: Vec<WatchedFolder>,
+    /// If set every new log is parsed and reported if it is at least this severe, otherwise only logs
+    /// with an outcome in the filename (eg. "[Error]") are reported
+    #[serde(default)]
+    min_severity: Option<Severity>,
+    #[serde(skip)]
+    is_changed: bool,
+}
+
+/// A folder that FreeFileSync writes logs into and the progress made processing it
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct WatchedFolder {
+    display_name: String,
     logs_dir: PathBuf,
     latest_log_datetime: NaiveDateTime,
     allowed_num_hours_without_log: Option<i64>,
+    /// Keyed by the profile name from the start of the log filenames
+    #[serde(default)]
+    profiles: BTreeMap<String, ProfileState>,
+    /// Glob patterns for the filenames to consider (all files if empty)
+    #[serde(default)]
+    include_patterns: Vec<String>,
+    /// Glob patterns for the filenames to ignore, takes precedence over `include_patterns`
+    #[serde(default)]
+    exclude_patterns: Vec<String>,
+    /// If files that are not excluded but are not recognised as logs should be reported
+    #[serde(default)]
+    report_unrecognised_files: bool,
+    /// How many levels of subfolders to search (0 for only the files directly in `logs_dir`)
+    #[serde(default)]
+    max_depth: usize,
+    #[serde(default)]
+    symlink_policy: SymlinkPolicy,
+    /// Logs this many hours older than `latest_log_datetime` that were not processed yet are still
+    /// reported (eg. a delayed copy or a restored file)
+    #[serde(default = "WatchedFolder::default_late_log_lookback_hours")]
+    late_log_lookback_hours: u32,
+    /// The logs processed within the lookback window
+    #[serde(default)]
+    processed_logs: BTreeSet<LogIdentity>,
+    /// Logs from before this are not in `processed_logs` (None for state files from before they
+    /// were tracked until the next run)
+    #[serde(default)]
+    processed_logs_since: Option<NaiveDateTime>,
     #[serde(skip)]
     is_changed: bool,
 }
+
+/// How symlinks found while searching a watched folder are handled
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    #[default]
+    Ignore,
+    /// Treat the symlink as the file or folder it points to (each folder is only searched once)
+    Follow,
+}
+
+/// Tracking for a single FreeFileSync profile (batch job) writing logs into a watched folder
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileState {
+    latest_log_datetime: NaiveDateTime,
+    allowed_num_hours_without_log: Option<i64>,
+}
+
+/// Identifies a log so that one arriving after newer logs can be told apart from those already processed
+#[derive(
+    Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(deny_unknown_fields)]
+pub struct LogIdentity {
+    /// From the filename, first so that the set is ordered by age
+    pub date_time: NaiveDateTime,
+    /// Relative to the watched folder
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// A new log found in a watched folder that has not yet been marked as processed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeenLog {
+    pub profile: String,
+    pub identity: LogIdentity,
+}
+
+/// Format of the state file from before multiple folders were supported
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields, rename = "AppState")]
+struct LegacyAppState {
+    last_alive_msg: DateTime<Local>,
+    alive_msg_time: Option<NaiveTime>,
+    logs_dir: PathBuf,
+    latest_log_datetime: NaiveDateTime,
+    allowed_num_hours_without_log: Option<i64>,
+    #[serde(default)]
+    min_severity: Option<Severity>,
+}
+
 impl AppState {
     pub fn is_changed(&self) -> bool {
-        self.is_changed
+        self.is_changed || self.watched_folders.iter().any(|x| x.is_changed)
     }
 
     pub(crate) fn save<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
@@ -41,18 +138,28 @@ impl AppState {
             )
         })?;
         self.is_changed = false;
+        self.watched_folders
+            .iter_mut()
+            .for_each(|x| x.is_changed = false);
         Ok(())
     }
 
     pub(crate) fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
         let s = fs::read_to_string(&path)
             .with_context(|| format!("failed to read file for AppState: {:?}", path.as_ref()))?;
-        ron::from_str(&s).with_context(|| {
-            format!(
-                "failed to deserialize AppState from contents of {:?}",
-                path.as_ref(),
-            )
-        })
+        ron::from_str(&s)
+            .or_else(|e| {
+                // Fall back to the format with only one folder and convert it if that works
+                ron::from_str::<LegacyAppState>(&s)
+                    .map(Into::into)
+                    .map_err(|_| e)
+            })
+            .with_context(|| {
+                format!(
+                    "failed to deserialize AppState from contents of {:?}",
+                    path.as_ref(),
+                )
+            })
     }
 
     pub fn new(logs_dir: PathBuf) -> Self {
@@ -62,9 +169,11 @@ impl AppState {
                 NaiveTime::from_hms_opt(7, 0, 0)
                     .expect("should be valid as it is set at build time"),
             ),
-            allowed_num_hours_without_log: Some(24),
-            latest_log_datetime: Local::now().naive_local(),
-            logs_dir,
+            watched_folders: vec![WatchedFolder::new(
+                WatchedFolder::default_display_name(&logs_dir),
+                logs_dir,
+            )],
+            min_severity: None,
             is_changed: Default::default(),
         }
     }
@@ -72,7 +181,10 @@ impl AppState {
     pub fn new_with_min_dates(logs_dir: PathBuf) -> Self {
         let mut result = Self::new(logs_dir);
         result.last_alive_msg = NaiveDateTime::MIN.and_local_timezone(Local).unwrap();
-        result.latest_log_datetime = NaiveDateTime::MIN;
+        result.watched_folders.iter_mut().for_each(|x| {
+            x.latest_log_datetime = NaiveDateTime::MIN;
+            x.processed_logs_since = Some(NaiveDateTime::MIN);
+        });
         result
     }
 
@@ -101,6 +213,91 @@ impl AppState {
         }
     }
 
+    pub fn watched_folders(&self) -> &[WatchedFolder] {
+        &self.watched_folders
+    }
+
+    pub fn watched_folders_mut(&mut self) -> &mut [WatchedFolder] {
+        &mut self.watched_folders
+    }
+
+    pub fn add_watched_folder(&mut self, folder: WatchedFolder) {
+        self.is_changed = true;
+        self.watched_folders.push(folder);
+    }
+
+    pub fn min_severity(&self) -> Option<Severity> {
+        self.min_severity
+    }
+
+    pub fn set_min_severity(&mut self, value: Option<Severity>) {
+        self.is_changed = true;
+        self.min_severity = value;
+    }
+
+    /// Records the logs found by [`crate::process_logs_folders`] so they are not reported again
+    ///
+    /// `seen_logs` is expected to be in the same order as the watched folders
+    pub fn mark_logs_processed(&mut self, seen_logs: &[Vec<SeenLog>]) {
+        for (folder, folder_seen_logs) in self.watched_folders.iter_mut().zip(seen_logs) {
+            folder.mark_logs_processed(folder_seen_logs);
+        }
+    }
+
+    /// Returns one message per folder that has gone too long without a log
+    pub(crate) fn generate_inactivity_msgs(&self) -> Vec<String> {
+        self.watched_folders
+            .iter()
+            .flat_map(|x| x.generate_inactivity_msgs())
+            .collect()
+    }
+}
+
+impl WatchedFolder {
+    pub fn new(display_name: String, logs_dir: PathBuf) -> Self {
+        let latest_log_datetime = Local::now().naive_local();
+        Self {
+            display_name,
+            logs_dir,
+            latest_log_datetime,
+            allowed_num_hours_without_log: Some(24),
+            profiles: Default::default(),
+            include_patterns: Default::default(),
+            exclude_patterns: Default::default(),
+            report_unrecognised_files: Default::default(),
+            max_depth: Default::default(),
+            symlink_policy: Default::default(),
+            late_log_lookback_hours: Self::default_late_log_lookback_hours(),
+            processed_logs: Default::default(),
+            processed_logs_since: Some(latest_log_datetime),
+            is_changed: Default::default(),
+        }
+    }
+
+    pub fn new_with_min_dates(display_name: String, logs_dir: PathBuf) -> Self {
+        let mut result = Self::new(display_name, logs_dir);
+        result.latest_log_datetime = NaiveDateTime::MIN;
+        result.processed_logs_since = Some(NaiveDateTime::MIN);
+        result
+    }
+
+    fn default_late_log_lookback_hours() -> u32 {
+        72
+    }
+
+    /// Uses the name of the folder or the full path if it doesn't have one
+    fn default_display_name(logs_dir: &Path) -> String {
+        logs_dir
+            .file_name()
+            .unwrap_or(logs_dir.as_os_str())
+            .to_string_lossy()
+            .to_string()
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.display_name
+    }
+
     pub fn logs_dir(&self) -> &Path {
         &self.logs_dir
     }
@@ -114,16 +311,239 @@ impl AppState {
         self.latest_log_datetime = value;
     }
 
-    pub(crate) fn generate_inactivity_msg(&self) -> Option<String> {
-        let allowed_hours = self.allowed_num_hours_without_log?;
-        let num_hours_since_log = Local::now()
-            .naive_local()
-            .signed_duration_since(self.latest_log_datetime)
-            .num_hours();
-        if num_hours_since_log > allowed_hours {
-            Some(format!("Most recent log found ({}) exceeds the allowed number of hours ({allowed_hours}) without a log. Currently {num_hours_since_log} hours without a log.", self.latest_log_datetime.format("%F %T")))
-        } else {
-            None
+    pub fn set_include_patterns(&mut self, value: Vec<String>) {
+        self.is_changed = true;
+        self.include_patterns = value;
+    }
+
+    pub fn set_exclude_patterns(&mut self, value: Vec<String>) {
+        self.is_changed = true;
+        self.exclude_patterns = value;
+    }
+
+    pub fn report_unrecognised_files(&self) -> bool {
+        self.report_unrecognised_files
+    }
+
+    pub fn set_report_unrecognised_files(&mut self, value: bool) {
+        self.is_changed = true;
+        self.report_unrecognised_files = value;
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn set_max_depth(&mut self, value: usize) {
+        self.is_changed = true;
+        self.max_depth = value;
+    }
+
+    pub fn symlink_policy(&self) -> SymlinkPolicy {
+        self.symlink_policy
+    }
+
+    pub fn set_symlink_policy(&mut self, value: SymlinkPolicy) {
+        self.is_changed = true;
+        self.symlink_policy = value;
+    }
+
+    pub(crate) fn file_patterns(&self) -> anyhow::Result<FilePatterns> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|x| Pattern::new(x).with_context(|| format!("invalid file pattern: {x:?}")))
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+        Ok(FilePatterns {
+            include: compile(&self.include_patterns)?,
+            exclude: compile(&self.exclude_patterns)?,
+        })
+    }
+
+    pub fn profiles(&self) -> &BTreeMap<String, ProfileState> {
+        &self.profiles
+    }
+
+    /// Records that a log was found for the profile, new profiles use the folder's allowed number of hours
+    ///
+    /// Logs without a profile in their filename are only tracked by the folder as a whole
+    pub fn record_profile_log(&mut self, profile: &str, log_datetime: NaiveDateTime) {
+        if profile.is_empty() {
+            return;
+        }
+        match self.profiles.get_mut(profile) {
+            Some(profile_state) => {
+                if log_datetime > profile_state.latest_log_datetime {
+                    self.is_changed = true;
+                    profile_state.latest_log_datetime = log_datetime;
+                }
+            }
+            None => {
+                self.is_changed = true;
+                self.profiles.insert(
+                    profile.to_string(),
+                    ProfileState {
+                        latest_log_datetime: log_datetime,
+                        allowed_num_hours_without_log: self.allowed_num_hours_without_log,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Records the profiles and identities of the logs and moves the latest log date forward to
+    /// the newest of them. Identities that are now outside of the lookback window are dropped.
+    pub fn mark_logs_processed(&mut self, seen_logs: &[SeenLog]) {
+        if self.processed_logs_since.is_none() {
+            // Logs already processed before identities were tracked are not in the set
+            self.is_changed = true;
+            self.processed_logs_since = Some(self.latest_log_datetime);
+        }
+        for seen_log in seen_logs {
+            self.record_profile_log(&seen_log.profile, seen_log.identity.date_time);
+            self.is_changed = true;
+            self.processed_logs.insert(seen_log.identity.clone());
+        }
+        if let Some(latest) = seen_logs.iter().map(|x| x.identity.date_time).max() {
+            if latest > self.latest_log_datetime {
+                self.set_latest_log_datetime(latest);
+            }
+        }
+        if let Some(window_start) = self.late_log_window_start() {
+            let count_before = self.processed_logs.len();
+            self.processed_logs.retain(|x| x.date_time > window_start);
+            if self.processed_logs.len() != count_before {
+                self.is_changed = true;
+            }
+        }
+    }
+
+    pub fn late_log_lookback_hours(&self) -> u32 {
+        self.late_log_lookback_hours
+    }
+
+    pub fn set_late_log_lookback_hours(&mut self, value: u32) {
+        self.is_changed = true;
+        self.late_log_lookback_hours = value;
+    }
+
+    pub fn processed_logs(&self) -> &BTreeSet<LogIdentity> {
+        &self.processed_logs
+    }
+
+    /// Logs after this that are not newer than `latest_log_datetime` may have arrived late (None if
+    /// identities are not tracked yet)
+    fn late_log_window_start(&self) -> Option<NaiveDateTime> {
+        let lookback_start = self
+            .latest_log_datetime
+            .checked_sub_signed(TimeDelta::hours(self.late_log_lookback_hours.into()))
+            .unwrap_or(NaiveDateTime::MIN);
+        self.processed_logs_since.map(|x| x.max(lookback_start))
+    }
+
+    /// If the log has not been processed yet, either because it is newer than the latest log or
+    /// it arrived late and is within the lookback window
+    pub(crate) fn is_unprocessed(&self, identity: &LogIdentity) -> bool {
+        if identity.date_time > self.latest_log_datetime {
+            return true;
+        }
+        match self.late_log_window_start() {
+            Some(window_start) => {
+                identity.date_time > window_start && !self.processed_logs.contains(identity)
+            }
+            None => false,
+        }
+    }
+
+    /// Only reports the profiles individually if the folder as a whole is not inactive
+    fn generate_inactivity_msgs(&self) -> Vec<String> {
+        if let Some(num_hours_since_log) =
+            hours_exceeded(self.latest_log_datetime, self.allowed_num_hours_without_log)
+        {
+            return vec![format!("Most recent log found in {} ({}) exceeds the allowed number of hours ({}) without a log. Currently {num_hours_since_log} hours without a log.", self.display_name, self.latest_log_datetime.format("%F %T"), self.allowed_num_hours_without_log.unwrap_or_default())];
+        }
+        self.profiles
+            .iter()
+            // State files from before logs without a profile were skipped may have an empty name
+            .filter(|(name, _)| !name.is_empty())
+            .filter_map(|(name, profile_state)| {
+                let num_hours_since_log = hours_exceeded(
+                    profile_state.latest_log_datetime,
+                    profile_state.allowed_num_hours_without_log,
+                )?;
+                Some(format!("{name} in {} hasn't run in {num_hours_since_log} hours. Most recent log found ({}) exceeds the allowed number of hours ({}).", self.display_name, profile_state.latest_log_datetime.format("%F %T"), profile_state.allowed_num_hours_without_log.unwrap_or_default()))
+            })
+            .collect()
+    }
+}
+
+impl ProfileState {
+    pub fn latest_log_datetime(&self) -> NaiveDateTime {
+        self.latest_log_datetime
+    }
+
+    pub fn allowed_num_hours_without_log(&self) -> Option<i64> {
+        self.allowed_num_hours_without_log
+    }
+}
+
+/// Compiled version of the patterns set on a [`WatchedFolder`]
+pub(crate) struct FilePatterns {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl FilePatterns {
+    /// True if the filename should be considered as a possible log
+    pub(crate) fn is_match(&self, file_name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|x| x.matches(file_name)))
+            && !self.exclude.iter().any(|x| x.matches(file_name))
+    }
+}
+
+/// Returns the number of hours since the log if it is more than allowed
+fn hours_exceeded(latest_log_datetime: NaiveDateTime, allowed_hours: Option<i64>) -> Option<i64> {
+    let allowed_hours = allowed_hours?;
+    let num_hours_since_log = Local::now()
+        .naive_local()
+        .signed_duration_since(latest_log_datetime)
+        .num_hours();
+    (num_hours_since_log > allowed_hours).then_some(num_hours_since_log)
+}
+
+impl From<LegacyAppState> for AppState {
+    fn from(value: LegacyAppState) -> Self {
+        let LegacyAppState {
+            last_alive_msg,
+            alive_msg_time,
+            logs_dir,
+            latest_log_datetime,
+            allowed_num_hours_without_log,
+            min_severity,
+        } = value;
+        Self {
+            last_alive_msg,
+            alive_msg_time,
+            watched_folders: vec![WatchedFolder {
+                display_name: WatchedFolder::default_display_name(&logs_dir),
+                logs_dir,
+                latest_log_datetime,
+                allowed_num_hours_without_log,
+                profiles: Default::default(),
+                include_patterns: Default::default(),
+                exclude_patterns: Default::default(),
+                report_unrecognised_files: Default::default(),
+                max_depth: Default::default(),
+                symlink_policy: Default::default(),
+                late_log_lookback_hours: WatchedFolder::default_late_log_lookback_hours(),
+                processed_logs: Default::default(),
+                processed_logs_since: None,
+                is_changed: true,
+            }],
+            min_severity,
+            // Ensure the file is saved in the new format
+            is_changed: true,
         }
     }
 }
@@ -0,0 +1,236 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// A request received by [`HttpStub`]
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercase
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl StubRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A response for [`HttpStub`] to return
+#[derive(Debug, Clone)]
+pub struct StubResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl StubResponse {
+    pub fn ok() -> Self {
+        Self::status(200)
+    }
+
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Default::default(),
+            body: Default::default(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+}
+
+/// Local HTTP server that answers one request per response given and records the requests
+pub struct HttpStub {
+    pub url: String,
+    requests: Arc<Mutex<Vec<StubRequest>>>,
+    handle: JoinHandle<()>,
+}
+
+impl HttpStub {
+    pub fn start(responses: Vec<StubResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handle = {
+            let requests = Arc::clone(&requests);
+            std::thread::spawn(move || {
+                for response in responses {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let request = read_request(&mut BufReader::new(&mut stream));
+                    requests.lock().unwrap().push(request);
+                    let mut reply = format!("HTTP/1.1 {} Stub\r\n", response.status);
+                    for (name, value) in response.headers.iter() {
+                        reply.push_str(&format!("{name}: {value}\r\n"));
+                    }
+                    reply.push_str(&format!(
+                        "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        response.body.len(),
+                        response.body
+                    ));
+                    stream.write_all(reply.as_bytes()).unwrap();
+                }
+            })
+        };
+        Self {
+            url,
+            requests,
+            handle,
+        }
+    }
+
+    /// Waits for all the responses to be used and returns the requests received
+    pub fn finish(self) -> Vec<StubRequest> {
+        self.handle.join().unwrap();
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> StubRequest {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        let Some((name, value)) = line.trim_end().split_once(':') else {
+            break; // Blank line at the end of the headers
+        };
+        headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+    }
+
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .map(|(_, value)| value.parse().unwrap())
+        .unwrap_or(0);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+    StubRequest {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    }
+}
+
+/// An email received by [`SmtpSink`]
+#[derive(Debug, Clone, Default)]
+pub struct SinkMail {
+    /// The AUTH commands sent by the client (including any continuation lines)
+    pub auth: Vec<String>,
+    pub mail_from: String,
+    pub rcpt_to: Vec<String>,
+    pub data: String,
+}
+
+/// Local SMTP server that accepts every email without TLS and passes them on as they are received
+pub struct SmtpSink {
+    pub port: u16,
+    mails: Receiver<SinkMail>,
+}
+
+impl SmtpSink {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, mails) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { break };
+                let tx = tx.clone();
+                std::thread::spawn(move || serve_smtp(stream, tx));
+            }
+        });
+        Self { port, mails }
+    }
+
+    /// Waits for the next email to be received
+    pub fn next_mail(&self) -> SinkMail {
+        self.mails
+            .recv_timeout(Duration::from_secs(10))
+            .expect("no email received")
+    }
+}
+
+fn serve_smtp(mut stream: TcpStream, tx: Sender<SinkMail>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut reply = |s: &str| stream.write_all(format!("{s}\r\n").as_bytes());
+    let mut read_line = || {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+        }
+    };
+    let _ = reply("220 localhost SMTP sink");
+    let mut mail = SinkMail::default();
+    while let Some(line) = read_line() {
+        let command = line.to_uppercase();
+        let result = if command.starts_with("EHLO") {
+            reply("250-localhost\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME")
+        } else if command.starts_with("AUTH LOGIN") {
+            mail.auth.push(line);
+            let _ = reply("334 VXNlcm5hbWU6");
+            mail.auth.extend(read_line());
+            let _ = reply("334 UGFzc3dvcmQ6");
+            mail.auth.extend(read_line());
+            reply("235 Authenticated")
+        } else if command.starts_with("AUTH") {
+            mail.auth.push(line);
+            reply("235 Authenticated")
+        } else if command.starts_with("MAIL FROM:") {
+            mail.mail_from = line["MAIL FROM:".len()..].to_string();
+            reply("250 OK")
+        } else if command.starts_with("RCPT TO:") {
+            mail.rcpt_to.push(line["RCPT TO:".len()..].to_string());
+            reply("250 OK")
+        } else if command == "DATA" {
+            let _ = reply("354 End data with <CR><LF>.<CR><LF>");
+            while let Some(data_line) = read_line() {
+                if data_line == "." {
+                    break;
+                }
+                mail.data.push_str(&data_line);
+                mail.data.push('\n');
+            }
+            let auth = mail.auth.clone();
+            let _ = tx.send(std::mem::take(&mut mail));
+            // Authentication lasts for the whole connection
+            mail.auth = auth;
+            reply("250 OK")
+        } else if command == "QUIT" {
+            let _ = reply("221 Bye");
+            break;
+        } else {
+            reply("250 OK")
+        };
+        if result.is_err() {
+            break;
+        }
+    }
+}
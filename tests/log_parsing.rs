@@ -1,23 +1,50 @@
-use std::path::{Path, PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use chrono::Local;
-use fs_log_monitor::{build_err_msg_from_logs, process_logs_folder, AppState};
+use fs_log_monitor::{
+    build_err_msg_from_logs, process_logs_folders, AppState, LogInfo, Severity, WatchedFolder,
+};
 
 fn samples_folder() -> PathBuf {
     Path::new("tests").join("sample_logs")
 }
 
+fn markup_samples_folder() -> PathBuf {
+    Path::new("tests").join("sample_logs_markup")
+}
+
+/// Processes the folders, ensures none of them failed or had problems and marks the logs found
+/// as processed
+fn process_logs(app_state: &mut AppState) -> Vec<LogInfo> {
+    let processed_logs = process_logs_folders(app_state);
+    assert!(
+        processed_logs.failures.is_empty(),
+        "{:?}",
+        processed_logs.failures
+    );
+    assert!(
+        processed_logs.notices.is_empty(),
+        "{:?}",
+        processed_logs.notices
+    );
+    app_state.mark_logs_processed(&processed_logs.seen_logs);
+    processed_logs.log_infos
+}
+
 #[test]
 fn no_files_pass_filter() {
     let before_app_state_created = Local::now().naive_local();
     let mut actual = AppState::new(samples_folder());
     let expected = actual.clone();
     assert!(
-        actual.latest_log_datetime() >= before_app_state_created,
+        actual.watched_folders()[0].latest_log_datetime() >= before_app_state_created,
         "date assumed to be now or later so that the sample logs from the past should not be included"
     );
 
-    let log_infos = process_logs_folder(&mut actual).unwrap();
+    let log_infos = process_logs(&mut actual);
     assert!(
         log_infos.is_empty(),
         "all samples should be in the past and filtered out"
@@ -30,12 +57,306 @@ fn no_files_pass_filter() {
 fn output_snapshot() {
     let mut app_state = AppState::new_with_min_dates(samples_folder());
 
-    let logs_infos = process_logs_folder(&mut app_state).unwrap();
-    insta::assert_ron_snapshot!(logs_infos);
+    let logs_infos = process_logs(&mut app_state);
+    insta::assert_ron_snapshot!(logs_infos, {
+        "[].path" => "[path]",
+    });
     insta::assert_ron_snapshot!(app_state, {
         ".last_alive_msg" => "date_time",
+        ".watched_folders[].processed_logs[].size" => "[size]",
     });
 
-    let msg = build_err_msg_from_logs(logs_infos);
+    let msg = build_err_msg_from_logs(&logs_infos);
     insta::assert_snapshot!(msg);
 }
+
+#[test]
+fn markup_in_messages_snapshot() {
+    let mut app_state = AppState::new_with_min_dates(markup_samples_folder());
+
+    let logs_infos = process_logs(&mut app_state);
+    insta::assert_ron_snapshot!(logs_infos, {
+        "[].path" => "[path]",
+    });
+}
+
+#[test]
+fn min_severity_filters_all_logs() {
+    let mut app_state = AppState::new_with_min_dates(samples_folder());
+
+    app_state.set_min_severity(Some(Severity::Info));
+    let log_infos = process_logs(&mut app_state.clone());
+    assert_eq!(log_infos.len(), 7, "all logs should be reported");
+    let successful_log = log_infos
+        .iter()
+        .find(|x| x.abnormal_outcome.is_none())
+        .expect("log without outcome in filename should be included");
+    assert!(successful_log.summary.is_some());
+    assert_eq!(successful_log.severity(), Severity::Info);
+
+    app_state.set_min_severity(Some(Severity::Warning));
+    let log_infos = process_logs(&mut app_state);
+    assert_eq!(
+        log_infos.len(),
+        6,
+        "only logs with at least a warning should be reported"
+    );
+}
+
+#[test]
+fn multiple_folders_processed_together() {
+    let mut app_state = AppState::new_with_min_dates(samples_folder());
+    app_state.add_watched_folder(WatchedFolder::new_with_min_dates(
+        "Markup".to_string(),
+        markup_samples_folder(),
+    ));
+    app_state.add_watched_folder(WatchedFolder::new_with_min_dates(
+        "Missing".to_string(),
+        Path::new("tests").join("folder_that_does_not_exist"),
+    ));
+
+    let processed_logs = process_logs_folders(&app_state);
+    app_state.mark_logs_processed(&processed_logs.seen_logs);
+    assert_eq!(
+        processed_logs.failures.len(),
+        1,
+        "missing folder should fail"
+    );
+    let sources: Vec<&str> = processed_logs
+        .log_infos
+        .iter()
+        .map(|x| x.source.as_str())
+        .collect();
+    assert_eq!(
+        sources,
+        [
+            "sample_logs",
+            "sample_logs",
+            "sample_logs",
+            "sample_logs",
+            "sample_logs",
+            "sample_logs",
+            "Markup"
+        ]
+    );
+    let latest_log_datetimes: Vec<String> = app_state
+        .watched_folders()
+        .iter()
+        .map(|x| x.latest_log_datetime().to_string())
+        .collect();
+    assert_eq!(
+        latest_log_datetimes,
+        [
+            "2024-11-14 08:30:15",
+            "2024-12-02 10:15:00",
+            "-262143-01-01 00:00:00"
+        ],
+        "failed folder should not be changed"
+    );
+}
+
+#[test]
+fn profiles_tracked_from_filenames() {
+    let mut app_state = AppState::new_with_min_dates(samples_folder());
+    app_state.add_watched_folder(WatchedFolder::new_with_min_dates(
+        "Markup".to_string(),
+        markup_samples_folder(),
+    ));
+
+    let log_infos = process_logs(&mut app_state);
+    let profiles: Vec<&str> = log_infos.iter().map(|x| x.profile.as_str()).collect();
+    assert_eq!(
+        profiles,
+        [
+            "ProfileName",
+            "ProfileName",
+            "ProfileName",
+            "ProfileName",
+            "ProfileName",
+            "ProfileName",
+            "Nightly backup"
+        ]
+    );
+    let tracked_profiles: Vec<(&str, String)> = app_state
+        .watched_folders()
+        .iter()
+        .flat_map(|x| x.profiles())
+        .map(|(name, profile_state)| {
+            (
+                name.as_str(),
+                profile_state.latest_log_datetime().to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        tracked_profiles,
+        [
+            ("ProfileName", "2024-11-14 08:30:15".to_string()),
+            ("Nightly backup", "2024-12-02 10:15:00".to_string())
+        ]
+    );
+}
+
+#[test]
+fn logs_without_profile_not_tracked_as_profile() {
+    let mut folder = WatchedFolder::new_with_min_dates("Folder".to_string(), samples_folder());
+    let log_datetime = "2024-11-14T08:30:15".parse().unwrap();
+
+    folder.record_profile_log("", log_datetime);
+    folder.record_profile_log("ProfileName", log_datetime);
+
+    let profiles: Vec<&String> = folder.profiles().keys().collect();
+    assert_eq!(profiles, ["ProfileName"]);
+}
+
+#[test]
+fn unrecognised_files_skipped() {
+    let mut app_state = AppState::new_with_min_dates(markup_samples_folder());
+    let folder = &mut app_state.watched_folders_mut()[0];
+    folder.set_exclude_patterns(vec!["*.tmp".to_string()]);
+    folder.set_report_unrecognised_files(true);
+
+    let processed_logs = process_logs_folders(&app_state);
+    assert!(processed_logs.failures.is_empty());
+    assert_eq!(processed_logs.log_infos.len(), 1);
+    assert_eq!(processed_logs.notices.len(), 1);
+    let notice = &processed_logs.notices[0];
+    assert!(notice.starts_with("1 unrecognised files present in sample_logs_markup"));
+    assert!(notice.contains("desktop.ini"));
+    assert!(!notice.contains("notes.tmp"), "excluded files are ignored");
+}
+
+#[test]
+fn nested_folders_searched_to_max_depth() {
+    let sources_found = |max_depth| {
+        let mut app_state =
+            AppState::new_with_min_dates(Path::new("tests").join("sample_logs_nested"));
+        app_state.watched_folders_mut()[0].set_max_depth(max_depth);
+        process_logs(&mut app_state)
+            .into_iter()
+            .map(|x| x.source)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(sources_found(0), ["sample_logs_nested"]);
+    assert_eq!(
+        sources_found(1),
+        ["sample_logs_nested", "sample_logs_nested/Offsite"]
+    );
+    assert_eq!(
+        sources_found(2),
+        [
+            "sample_logs_nested",
+            "sample_logs_nested/Offsite",
+            "sample_logs_nested/Offsite/2024-11"
+        ]
+    );
+}
+
+#[test]
+fn late_arrivals_within_lookback_reported() {
+    let logs_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("late_arrivals");
+    let _ = fs::remove_dir_all(&logs_dir);
+    fs::create_dir_all(&logs_dir).unwrap();
+    let copy_sample = |file_name: &str| {
+        fs::copy(samples_folder().join(file_name), logs_dir.join(file_name)).unwrap();
+    };
+    let mut app_state = AppState::new_with_min_dates(logs_dir.clone());
+
+    copy_sample("ProfileName 2024-11-14 083015.120 [Error].zip");
+    assert_eq!(process_logs(&mut app_state).len(), 1);
+
+    // Within the lookback window of the latest log and outside of it
+    copy_sample("ProfileName 2024-11-12 083015.120 [Error].log");
+    copy_sample("ProfileName 2024-11-08 140913.247 [Error].html");
+    let processed_logs = process_logs_folders(&app_state);
+    app_state.mark_logs_processed(&processed_logs.seen_logs);
+    let dates: Vec<String> = processed_logs
+        .log_infos
+        .iter()
+        .map(|x| x.date_time.to_string())
+        .collect();
+    assert_eq!(dates, ["2024-11-12 08:30:15"]);
+    assert_eq!(processed_logs.notices.len(), 1);
+    assert!(processed_logs.notices[0]
+        .starts_with("1 logs arrived after newer logs were processed in late_arrivals"));
+
+    assert!(
+        process_logs(&mut app_state).is_empty(),
+        "late logs should only be reported once"
+    );
+    assert_eq!(app_state.watched_folders()[0].processed_logs().len(), 2);
+}
+
+#[test]
+fn errors_found_without_heading() {
+    let logs_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("errors_without_heading");
+    let _ = fs::remove_dir_all(&logs_dir);
+    fs::create_dir_all(&logs_dir).unwrap();
+    let file_name = "ProfileName 2024-11-08 140913.247 [Error].html";
+    let contents = fs::read_to_string(samples_folder().join(file_name)).unwrap();
+    fs::write(
+        logs_dir.join(file_name),
+        contents.replace("Errors and warnings:", "Fehler und Warnungen:"),
+    )
+    .unwrap();
+    let mut app_state = AppState::new_with_min_dates(logs_dir.clone());
+    let expected = process_logs(&mut AppState::new_with_min_dates(samples_folder()))
+        .into_iter()
+        .find(|x| x.path.as_ref().unwrap().ends_with(file_name))
+        .unwrap()
+        .errors_and_warnings;
+
+    let log_infos = process_logs(&mut app_state);
+
+    assert_eq!(log_infos[0].errors_and_warnings, expected);
+
+    // No entries found while the summary says there are some
+    let without_table = contents.replace("log-items", "renamed-items");
+    fs::write(logs_dir.join(file_name), without_table).unwrap();
+    let processed_logs = process_logs_folders(&AppState::new_with_min_dates(logs_dir));
+    assert!(processed_logs.failures.is_empty());
+    assert_eq!(processed_logs.notices.len(), 1);
+    assert!(
+        processed_logs.notices[0]
+            .contains("summary has 4 errors and 0 warnings but no entries were found"),
+        "{}",
+        processed_logs.notices[0]
+    );
+}
+
+#[test]
+fn log_without_summary_reported_with_note() {
+    let logs_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("log_without_summary");
+    let _ = fs::remove_dir_all(&logs_dir);
+    fs::create_dir_all(&logs_dir).unwrap();
+    let valid_name = "ProfileName 2024-11-08 140913.247 [Error].html";
+    let no_summary_name = "ProfileName 2024-11-08 145021.053 [Stopped].html";
+    fs::copy(samples_folder().join(valid_name), logs_dir.join(valid_name)).unwrap();
+    let contents = fs::read_to_string(samples_folder().join(no_summary_name)).unwrap();
+    fs::write(
+        logs_dir.join(no_summary_name),
+        contents.replace("summary-table", "renamed-table"),
+    )
+    .unwrap();
+    let mut app_state = AppState::new_with_min_dates(logs_dir);
+
+    let processed_logs = process_logs_folders(&app_state);
+    app_state.mark_logs_processed(&processed_logs.seen_logs);
+
+    assert!(processed_logs.failures.is_empty());
+    let summaries_found: Vec<bool> = processed_logs
+        .log_infos
+        .iter()
+        .map(|x| x.summary.is_some())
+        .collect();
+    assert_eq!(summaries_found, [true, false]);
+    assert_eq!(processed_logs.notices.len(), 1);
+    assert!(processed_logs.notices[0].contains("summary table not found"));
+    assert_eq!(
+        app_state.watched_folders()[0]
+            .latest_log_datetime()
+            .to_string(),
+        "2024-11-08 14:50:21"
+    );
+}
@@ -0,0 +1,1138 @@
+use std::{
+    cell::{Cell, RefCell},
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use chrono::TimeDelta;
+use common::{HttpStub, SmtpSink, StubResponse};
+use fs_log_monitor::{
+    build_err_msg_from_logs,
+    notification::{
+        send_to_channels, Channel, ChannelMode, Discord, DiscordConfig, Email, EmailConfig,
+        EmailRoute, EmailTransport, Gotify, GotifyConfig, Message, NotificationConfig, Notifier,
+        Ntfy, NtfyConfig, RetryPolicy, Slack, SmtpAuth, TlsMode, Webhook, WebhookConfig,
+    },
+    process_logs_folders, report_new_logs, AppState, Delivery, LogEntry, Outbox, Severity,
+};
+
+mod common;
+
+/// Records the names of the notifiers that were used in the order they were used
+type CallLog = Rc<RefCell<Vec<String>>>;
+
+struct FakeNotifier {
+    name: String,
+    should_fail: bool,
+    calls: CallLog,
+}
+
+impl Notifier for FakeNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn send(&self, _msg: &Message) -> anyhow::Result<()> {
+        self.calls.borrow_mut().push(self.name.clone());
+        if self.should_fail {
+            bail!("{} failed", self.name)
+        }
+        Ok(())
+    }
+}
+
+fn channel(
+    name: &str,
+    mode: ChannelMode,
+    min_severity: Option<Severity>,
+    should_fail: bool,
+    calls: &CallLog,
+) -> Channel {
+    Channel {
+        mode,
+        min_severity,
+        notifier: Box::new(FakeNotifier {
+            name: name.to_string(),
+            should_fail,
+            calls: Rc::clone(calls),
+        }),
+    }
+}
+
+#[test]
+fn failover_stops_at_first_success() {
+    let calls = CallLog::default();
+    let channels = [
+        channel("a", ChannelMode::Failover, None, true, &calls),
+        channel("b", ChannelMode::Failover, None, false, &calls),
+        channel("c", ChannelMode::Failover, None, false, &calls),
+    ];
+
+    send_to_channels(&Message::new(Severity::Error, "test"), &channels).unwrap();
+
+    assert_eq!(*calls.borrow(), ["a", "b"]);
+}
+
+#[test]
+fn all_mode_always_sent() {
+    let calls = CallLog::default();
+    let channels = [
+        channel("a", ChannelMode::Failover, None, false, &calls),
+        channel("b", ChannelMode::All, None, true, &calls),
+        channel("c", ChannelMode::All, None, false, &calls),
+    ];
+
+    send_to_channels(&Message::new(Severity::Info, "test"), &channels).unwrap();
+
+    assert_eq!(*calls.borrow(), ["b", "c", "a"]);
+}
+
+#[test]
+fn min_severity_skips_channel() {
+    let calls = CallLog::default();
+    let channels = [
+        channel("a", ChannelMode::All, Some(Severity::Error), false, &calls),
+        channel(
+            "b",
+            ChannelMode::Failover,
+            Some(Severity::Warning),
+            false,
+            &calls,
+        ),
+        channel("c", ChannelMode::Failover, None, false, &calls),
+    ];
+
+    send_to_channels(&Message::new(Severity::Info, "test"), &channels).unwrap();
+    assert_eq!(*calls.borrow(), ["c"]);
+
+    calls.borrow_mut().clear();
+    send_to_channels(&Message::new(Severity::Error, "test"), &channels).unwrap();
+    assert_eq!(*calls.borrow(), ["a", "b"]);
+}
+
+#[test]
+fn error_if_nothing_delivered() {
+    let calls = CallLog::default();
+    let channels = [
+        channel("a", ChannelMode::All, None, true, &calls),
+        channel("b", ChannelMode::Failover, None, true, &calls),
+    ];
+
+    assert!(send_to_channels(&Message::new(Severity::Error, "test"), &channels).is_err());
+    assert_eq!(*calls.borrow(), ["a", "b"]);
+}
+
+/// A message like the one sent after processing the sample logs
+fn sample_logs_msg() -> Message {
+    let app_state = AppState::new_with_min_dates(Path::new("tests").join("sample_logs"));
+    let processed_logs = process_logs_folders(&app_state);
+    assert!(processed_logs.failures.is_empty());
+    let log_infos = processed_logs.log_infos;
+    Message {
+        severity: Severity::Error,
+        text: build_err_msg_from_logs(&log_infos),
+        log_infos,
+        notes: vec![
+            "Most recent log found in <folder> exceeds the allowed number of hours".to_string(),
+        ],
+    }
+}
+
+#[test]
+fn slack_payload_snapshot() {
+    let payload = Slack::build_payload(&sample_logs_msg());
+    insta::assert_snapshot!(serde_json::to_string_pretty(&payload).unwrap());
+}
+
+#[test]
+fn slack_payload_within_limits() {
+    let mut msg = sample_logs_msg();
+    let log_info = msg.log_infos[0].clone();
+    msg.log_infos = vec![log_info; 100];
+    msg.notes = vec!["x".repeat(5000)];
+
+    let payload = Slack::build_payload(&msg);
+
+    let blocks = payload["blocks"].as_array().unwrap();
+    assert_eq!(blocks.len(), 50);
+    // Summary line, 100 logs and the note do not fit in the 49 sections after the header
+    assert_eq!(
+        blocks[49]["text"]["text"],
+        "*TRUNCATED* 54 more sections not shown"
+    );
+    for block in blocks {
+        assert!(block["text"]["text"].as_str().unwrap().chars().count() <= 3000);
+    }
+}
+
+#[test]
+fn webhook_sends_filled_template() {
+    let stub = HttpStub::start(vec![StubResponse::ok()]);
+    let config = WebhookConfig {
+        url: format!("{}/hooks/abc", stub.url),
+        method: "PUT".to_string(),
+        headers: [("X-Api-Key".to_string(), "secret".to_string())].into(),
+        body_template: r#"{"title": "{{severity}} \"quoted\"", "count": "{{log_count}}", "sections": [{"text": "{{message}}"}]}"#.to_string(),
+    };
+    let mut msg = sample_logs_msg();
+    // Placeholders in the message are not replaced
+    msg.text = "line 1 \"with quotes\"\nline 2 {{severity}} {{log_count}}".to_string();
+
+    Webhook::new(&config, RetryPolicy::default())
+        .unwrap()
+        .send(&msg)
+        .unwrap();
+
+    let requests = stub.finish();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0];
+    assert_eq!(request.method, "PUT");
+    assert_eq!(request.path, "/hooks/abc");
+    assert_eq!(request.header("x-api-key"), Some("secret"));
+    assert_eq!(request.header("content-type"), Some("application/json"));
+    let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "title": "Error \"quoted\"",
+            "count": 6,
+            "sections": [{"text": "line 1 \"with quotes\"\nline 2 {{severity}} {{log_count}}"}],
+        })
+    );
+}
+
+#[test]
+fn webhook_rejects_invalid_template() {
+    let config = WebhookConfig {
+        url: "http://localhost".to_string(),
+        method: "POST".to_string(),
+        headers: Default::default(),
+        body_template: r#"{"text": {{message}}}"#.to_string(),
+    };
+    assert!(Webhook::new(&config, RetryPolicy::default()).is_err());
+}
+
+#[test]
+fn ntfy_priority_title_and_tags() {
+    let stub = HttpStub::start(vec![StubResponse::ok(); 3]);
+    let ntfy = Ntfy::new(
+        &NtfyConfig {
+            server_url: format!("{}/", stub.url),
+            topic: "backups".to_string(),
+            token: Some("tk_abc".to_string()),
+        },
+        RetryPolicy::default(),
+    );
+    let mut msg = sample_logs_msg();
+    msg.notes.clear();
+    let error_log = msg.log_infos[0].clone();
+    let stopped_log = msg
+        .log_infos
+        .iter()
+        .find(|x| x.abnormal_outcome.as_deref() == Some("[Stopped]"))
+        .unwrap()
+        .clone();
+
+    msg.log_infos = vec![error_log];
+    ntfy.send(&msg).unwrap();
+    msg.log_infos = vec![stopped_log];
+    ntfy.send(&msg).unwrap();
+    ntfy.send(&Message::new(Severity::Info, "still alive"))
+        .unwrap();
+
+    let requests = stub.finish();
+    assert_eq!(requests[0].header("authorization"), Some("Bearer tk_abc"));
+    let bodies: Vec<serde_json::Value> = requests
+        .iter()
+        .map(|x| serde_json::from_str(&x.body).unwrap())
+        .collect();
+    assert_eq!(bodies[0]["topic"], "backups");
+    assert_eq!(bodies[0]["priority"], 4);
+    assert_eq!(bodies[0]["title"], "sample_logs ProfileName [Error]");
+    assert_eq!(
+        bodies[0]["tags"],
+        serde_json::json!(["ProfileName", "error"])
+    );
+    assert_eq!(bodies[1]["priority"], 3);
+    assert_eq!(bodies[1]["title"], "sample_logs ProfileName [Stopped]");
+    assert_eq!(bodies[2]["priority"], 2);
+    assert_eq!(bodies[2]["message"], "still alive");
+}
+
+#[test]
+fn gotify_sends_to_message_endpoint() {
+    let stub = HttpStub::start(vec![StubResponse::ok()]);
+    let gotify = Gotify::new(
+        &GotifyConfig {
+            server_url: stub.url.clone(),
+            app_token: "app_token".to_string(),
+        },
+        RetryPolicy::default(),
+    );
+
+    gotify.send(&sample_logs_msg()).unwrap();
+
+    let requests = stub.finish();
+    assert_eq!(requests[0].path, "/message");
+    assert_eq!(requests[0].header("x-gotify-key"), Some("app_token"));
+    let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(body["priority"], 8);
+    assert_eq!(body["title"], "FS Log Monitor - 6 logs (Error)");
+}
+
+/// Sends to a local SMTP server on the port (not needed for other transports)
+fn local_email_config(smtp_port: Option<u16>, auth: SmtpAuth) -> EmailConfig {
+    EmailConfig {
+        from_name: "FS Log Monitor".to_string(),
+        pass: "secret".to_string(),
+        from_email: "monitor@example.com".to_string(),
+        to: vec!["admin@example.com".to_string()],
+        cc: Default::default(),
+        bcc: Default::default(),
+        routes: Default::default(),
+        attach_logs: false,
+        max_attachments_size: 10 * 1024 * 1024,
+        subject: "Backup report".to_string(),
+        transport: EmailTransport::Smtp,
+        smtp_host: Some("127.0.0.1".to_string()),
+        smtp_port,
+        tls: TlsMode::None,
+        auth,
+        username: Some("relay_user".to_string()),
+    }
+}
+
+#[test]
+fn email_via_local_relay_without_auth() {
+    let sink = SmtpSink::start();
+    let email = Email::new(
+        &local_email_config(Some(sink.port), SmtpAuth::None),
+        RetryPolicy::default(),
+    )
+    .unwrap();
+
+    email
+        .send(&Message::new(Severity::Error, "Something failed"))
+        .unwrap();
+
+    let mail = sink.next_mail();
+    assert!(mail.auth.is_empty());
+    assert_eq!(mail.mail_from, "<monitor@example.com>");
+    assert_eq!(mail.rcpt_to, ["<admin@example.com>"]);
+    assert!(
+        mail.data.contains("Subject: Backup report"),
+        "{}",
+        mail.data
+    );
+    assert!(mail.data.contains("Something failed"), "{}", mail.data);
+}
+
+#[test]
+fn email_auth_mechanisms() {
+    let sink = SmtpSink::start();
+    let msg = Message::new(Severity::Info, "test");
+
+    Email::new(
+        &local_email_config(Some(sink.port), SmtpAuth::Plain),
+        RetryPolicy::default(),
+    )
+    .unwrap()
+    .send(&msg)
+    .unwrap();
+    assert_eq!(
+        sink.next_mail().auth,
+        ["AUTH PLAIN AHJlbGF5X3VzZXIAc2VjcmV0"]
+    );
+
+    Email::new(
+        &local_email_config(Some(sink.port), SmtpAuth::Login),
+        RetryPolicy::default(),
+    )
+    .unwrap()
+    .send(&msg)
+    .unwrap();
+    assert_eq!(
+        sink.next_mail().auth,
+        ["AUTH LOGIN", "cmVsYXlfdXNlcg==", "c2VjcmV0"]
+    );
+}
+
+#[test]
+fn email_addresses_required_in_notification_config() {
+    let config_folder = Path::new(env!("CARGO_TARGET_TMPDIR")).join("email_addresses_required");
+    fs::create_dir_all(&config_folder).unwrap();
+    fs::write(
+        config_folder.join(NotificationConfig::FILENAME),
+        r#"NotificationConfig(channels: [(kind: Email((from_name: "FS Log Monitor", pass: "secret")))])"#,
+    )
+    .unwrap();
+    let error = format!(
+        "{:#}",
+        NotificationConfig::load(&config_folder).unwrap_err()
+    );
+    assert!(error.contains("from_email"), "{error}");
+}
+
+#[test]
+fn legacy_email_config_keeps_original_defaults() {
+    let config_folder = Path::new(env!("CARGO_TARGET_TMPDIR")).join("legacy_email_config");
+    fs::create_dir_all(&config_folder).unwrap();
+    fs::write(
+        config_folder.join("e.data"),
+        r#"{"from_name": "FS Log Monitor", "pass": "secret"}"#,
+    )
+    .unwrap();
+    let config = EmailConfig::load_legacy(&config_folder).unwrap();
+    assert_eq!(config.from_email, "wykies.notices@gmail.com");
+    assert_eq!(config.to, ["it@wykies.com"]);
+    assert_eq!(config.smtp_host.as_deref(), Some("smtp.gmail.com"));
+}
+
+#[test]
+fn email_routes_by_outcome() {
+    let sink = SmtpSink::start();
+    let mut config = local_email_config(Some(sink.port), SmtpAuth::None);
+    config.to = vec![
+        "team1@example.com".to_string(),
+        "team2@example.com".to_string(),
+    ];
+    config.cc = vec!["lead@example.com".to_string()];
+    config.bcc = vec!["archive@example.com".to_string()];
+    config.routes = vec![EmailRoute {
+        outcomes: vec!["[Stopped]".to_string()],
+        min_severity: None,
+        to: vec!["admin@example.com".to_string()],
+        cc: Default::default(),
+        bcc: Default::default(),
+    }];
+    let email = Email::new(&config, RetryPolicy::default()).unwrap();
+    let mut msg = sample_logs_msg();
+
+    email.send(&msg).unwrap();
+    let mail = sink.next_mail();
+    assert_eq!(
+        mail.rcpt_to,
+        [
+            "<team1@example.com>",
+            "<team2@example.com>",
+            "<lead@example.com>",
+            "<archive@example.com>"
+        ]
+    );
+    assert!(mail.data.contains("Cc: lead@example.com"), "{}", mail.data);
+    assert!(!mail.data.contains("archive@example.com"), "{}", mail.data);
+
+    msg.log_infos
+        .retain(|x| x.abnormal_outcome.as_deref() == Some("[Stopped]"));
+    email.send(&msg).unwrap();
+    assert_eq!(sink.next_mail().rcpt_to, ["<admin@example.com>"]);
+}
+
+#[test]
+fn email_html_with_attachments() {
+    let sink = SmtpSink::start();
+    let mut config = local_email_config(Some(sink.port), SmtpAuth::None);
+    config.attach_logs = true;
+    // Enough for the first two sample logs but not the third
+    config.max_attachments_size = 20_000;
+    let email = Email::new(&config, RetryPolicy::default()).unwrap();
+
+    email.send(&sample_logs_msg()).unwrap();
+
+    // Undo the soft line breaks from quoted-printable encoding
+    let data = sink.next_mail().data.replace("=\n", "");
+    assert!(data.contains("multipart/mixed"), "{data}");
+    assert!(data.contains("multipart/alternative"), "{data}");
+    assert!(data.contains("Content-Type: text/html"), "{data}");
+    assert!(data.contains("<table>"), "{data}");
+    assert_eq!(
+        data.matches("Content-Disposition: attachment").count(),
+        3,
+        "{data}"
+    );
+    assert!(
+        data.contains("ProfileName 2024-10-15 092845.903 [Error].html\""),
+        "{data}"
+    );
+    assert!(
+        data.contains("ProfileName 2024-11-08 145021.053 [Stopped].html not attached as the attachments would exceed 20000 bytes"),
+        "{data}"
+    );
+}
+
+#[test]
+fn email_written_to_spool_folder() {
+    let spool_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("email_spool");
+    let _ = fs::remove_dir_all(&spool_dir);
+    fs::create_dir_all(&spool_dir).unwrap();
+    let mut config = local_email_config(None, SmtpAuth::None);
+    config.transport = EmailTransport::Spool {
+        dir: spool_dir.clone(),
+    };
+
+    Email::new(&config, RetryPolicy::default())
+        .unwrap()
+        .send(&Message::new(Severity::Error, "Something failed"))
+        .unwrap();
+
+    let files: Vec<_> = fs::read_dir(&spool_dir)
+        .unwrap()
+        .map(|x| x.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].extension().unwrap(), "eml");
+    let contents = fs::read_to_string(&files[0]).unwrap();
+    assert!(contents.contains("To: admin@example.com"), "{contents}");
+    assert!(contents.contains("Something failed"), "{contents}");
+}
+
+#[cfg(unix)]
+#[test]
+fn email_passed_to_sendmail_command() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("email_sendmail");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let output_file = dir.join("mail.eml");
+    let command = dir.join("sendmail");
+    fs::write(
+        &command,
+        format!("#!/bin/sh\ncat > {:?}\n", output_file.display().to_string()),
+    )
+    .unwrap();
+    fs::set_permissions(&command, fs::Permissions::from_mode(0o755)).unwrap();
+    let mut config = local_email_config(None, SmtpAuth::None);
+    config.transport = EmailTransport::Sendmail {
+        command: Some(command.display().to_string()),
+    };
+
+    Email::new(&config, RetryPolicy::default())
+        .unwrap()
+        .send(&Message::new(Severity::Error, "Something failed"))
+        .unwrap();
+
+    let contents = fs::read_to_string(&output_file).unwrap();
+    assert!(contents.contains("To: admin@example.com"), "{contents}");
+    assert!(contents.contains("Something failed"), "{contents}");
+}
+
+#[test]
+fn discord_embeds_per_log() {
+    let stub = HttpStub::start(vec![StubResponse::ok(); 2]);
+    let discord = Discord::new(
+        &DiscordConfig {
+            webhook_url: stub.url.clone(),
+            use_embeds: true,
+        },
+        RetryPolicy::default(),
+    );
+
+    discord.send(&sample_logs_msg()).unwrap();
+    discord
+        .send(&Message::new(Severity::Info, "still alive"))
+        .unwrap();
+
+    let bodies: Vec<serde_json::Value> = stub
+        .finish()
+        .iter()
+        .map(|x| serde_json::from_str(&x.body).unwrap())
+        .collect();
+    assert_eq!(bodies[0]["content"], "6 logs with 10 error and warnings");
+    let embeds = bodies[0]["embeds"].as_array().unwrap();
+    // One per log plus the notes
+    assert_eq!(embeds.len(), 7);
+    assert_eq!(embeds[0]["title"], "sample_logs ProfileName [Error]");
+    assert_eq!(embeds[0]["color"], 0xE74C3C);
+    assert_eq!(embeds[0]["fields"][0]["value"], "2024-10-15 09:28:45");
+    assert_eq!(embeds[0]["fields"][1]["value"], "1");
+    assert_eq!(embeds[0]["fields"][2]["value"], "ProfileName");
+    assert_eq!(embeds[2]["title"], "sample_logs ProfileName [Stopped]");
+    assert_eq!(embeds[2]["color"], 0xE67E22);
+    assert_eq!(bodies[1]["embeds"][0]["description"], "still alive");
+    assert_eq!(bodies[1]["embeds"][0]["color"], 0x2ECC71);
+}
+
+#[test]
+fn discord_long_log_continued_in_more_embeds() {
+    let mut msg = sample_logs_msg();
+    let mut log_info = msg.log_infos[0].clone();
+    let entry = log_info.errors_and_warnings[0].clone();
+    log_info.errors_and_warnings = (0..300).map(|i| numbered_entry(&entry, i)).collect();
+    msg.log_infos = vec![log_info];
+    let discord = Discord::new(
+        &DiscordConfig {
+            webhook_url: Default::default(),
+            use_embeds: true,
+        },
+        RetryPolicy::default(),
+    );
+
+    let payloads = discord.build_payloads(&msg);
+
+    let embeds: Vec<&serde_json::Value> = payloads
+        .iter()
+        .flat_map(|x| x["embeds"].as_array().unwrap())
+        .collect();
+    assert!(embeds.len() > 2);
+    assert_eq!(embeds[0]["title"], "sample_logs ProfileName [Error]");
+    assert_eq!(
+        embeds[1]["title"],
+        "sample_logs ProfileName [Error] (continued)"
+    );
+    let descriptions: Vec<&str> = embeds
+        .iter()
+        .map(|x| x["description"].as_str().unwrap())
+        .collect();
+    for description in descriptions.iter() {
+        assert!(description.chars().count() <= 4096);
+    }
+    let entries_found = (0..300)
+        .filter(|i| {
+            descriptions
+                .iter()
+                .any(|x| x.contains(&format!("entry number {i};")))
+        })
+        .count();
+    assert_eq!(entries_found, 300, "no entries should be dropped");
+}
+
+/// A copy of the entry with a message that can be found again
+fn numbered_entry(entry: &LogEntry, i: usize) -> LogEntry {
+    LogEntry {
+        message: format!("entry number {i}; {}", entry.message),
+        ..entry.clone()
+    }
+}
+
+#[test]
+fn discord_long_report_split_on_log_boundaries() {
+    let mut msg = sample_logs_msg();
+    msg.log_infos = vec![msg.log_infos; 5].concat();
+    let discord = Discord::new(
+        &DiscordConfig {
+            webhook_url: Default::default(),
+            use_embeds: false,
+        },
+        RetryPolicy::default(),
+    );
+    let part_count = discord.build_payloads(&msg).len();
+    assert!(part_count > 1);
+    let stub = HttpStub::start(vec![StubResponse::ok(); part_count]);
+    let discord = Discord::new(
+        &DiscordConfig {
+            webhook_url: stub.url.clone(),
+            use_embeds: false,
+        },
+        RetryPolicy::default(),
+    );
+
+    discord.send(&msg).unwrap();
+
+    let contents: Vec<String> = stub
+        .finish()
+        .iter()
+        .map(|x| {
+            let body: serde_json::Value = serde_json::from_str(&x.body).unwrap();
+            body["content"].as_str().unwrap().to_string()
+        })
+        .collect();
+    for content in contents.iter() {
+        assert!(content.chars().count() <= 2000);
+        assert!(content.starts_with("---\n"), "{content}");
+    }
+    let log_count: usize = contents
+        .iter()
+        .map(|x| x.matches("---\nsample_logs ProfileName").count())
+        .sum();
+    assert_eq!(log_count, 30);
+    assert!(contents
+        .last()
+        .unwrap()
+        .contains("30 logs with 50 error and warnings"));
+}
+
+#[test]
+fn discord_split_report_failure_names_delivered_parts() {
+    let mut msg = sample_logs_msg();
+    msg.log_infos = vec![msg.log_infos; 5].concat();
+    let stub = HttpStub::start(vec![
+        StubResponse::ok(),
+        StubResponse::ok(),
+        StubResponse::status(400),
+    ]);
+    let discord = Discord::new(
+        &DiscordConfig {
+            webhook_url: stub.url.clone(),
+            use_embeds: false,
+        },
+        RetryPolicy::default(),
+    );
+    let part_count = discord.build_payloads(&msg).len();
+    assert!(part_count > 3);
+
+    let error = format!("{:#}", discord.send(&msg).unwrap_err());
+
+    assert!(
+        error.contains(&format!(
+            "failed to send part #3 of {part_count} of message (parts #1 to #2 already delivered)"
+        )),
+        "{error}"
+    );
+    // The parts after the failed one are not sent
+    assert_eq!(stub.finish().len(), 3);
+}
+
+#[test]
+fn discord_waits_as_told_when_rate_limited() {
+    let stub = HttpStub::start(vec![
+        StubResponse::status(429).header("Retry-After", "0.2").body(
+            r#"{"message": "You are being rate limited.", "retry_after": 0.2, "global": false}"#,
+        ),
+        StubResponse::ok(),
+    ]);
+    let discord = Discord::new(
+        &DiscordConfig {
+            webhook_url: stub.url.clone(),
+            use_embeds: false,
+        },
+        RetryPolicy::default(),
+    );
+    let start = Instant::now();
+
+    discord.send(&Message::new(Severity::Info, "test")).unwrap();
+
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(10), "{elapsed:?}");
+    assert_eq!(stub.finish().len(), 2);
+}
+
+#[test]
+fn discord_fails_fast_when_asked_to_wait_too_long() {
+    let stub = HttpStub::start(vec![StubResponse::status(429).header("Retry-After", "3600")]);
+    let discord = Discord::new(
+        &DiscordConfig {
+            webhook_url: stub.url.clone(),
+            use_embeds: false,
+        },
+        RetryPolicy::default(),
+    );
+    let start = Instant::now();
+
+    let result = discord.send(&Message::new(Severity::Info, "test"));
+
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(
+        error.contains(
+            "asked to wait 3600.000 seconds which is more than the maximum of 60 seconds"
+        ),
+        "{error}"
+    );
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(stub.finish().len(), 1);
+}
+
+#[test]
+fn discord_deleted_webhook_fails_over_without_retrying() {
+    let stub = HttpStub::start(vec![
+        StubResponse::status(404).body(r#"{"message": "Unknown Webhook", "code": 10015}"#)
+    ]);
+    let calls = CallLog::default();
+    let channels = [
+        Channel {
+            mode: ChannelMode::Failover,
+            min_severity: None,
+            notifier: Box::new(Discord::new(
+                &DiscordConfig {
+                    webhook_url: stub.url.clone(),
+                    use_embeds: false,
+                },
+                RetryPolicy::default(),
+            )),
+        },
+        channel("email", ChannelMode::Failover, None, false, &calls),
+    ];
+    let start = Instant::now();
+
+    send_to_channels(&Message::new(Severity::Error, "test"), &channels).unwrap();
+
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(stub.finish().len(), 1);
+    assert_eq!(*calls.borrow(), ["email"]);
+}
+
+fn fast_retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 4,
+        initial_delay_secs: 0.01,
+        backoff_factor: 1.0,
+        jitter: 0.5,
+        max_total_secs: None,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn retry_policy_backoff() {
+    let policy = RetryPolicy {
+        initial_delay_secs: 1.0,
+        backoff_factor: 2.0,
+        ..Default::default()
+    };
+    assert_eq!(policy.delay_before_retry(1), Duration::from_secs(1));
+    assert_eq!(policy.delay_before_retry(2), Duration::from_secs(2));
+    assert_eq!(policy.delay_before_retry(3), Duration::from_secs(4));
+}
+
+#[test]
+fn retry_policy_stops_after_max_attempts() {
+    let mut attempts = 0;
+    let result = fast_retry_policy().run("test", || {
+        attempts += 1;
+        bail!("failed")
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 4);
+
+    let mut attempts = 0;
+    fast_retry_policy()
+        .run("test", || {
+            attempts += 1;
+            if attempts < 2 {
+                bail!("failed")
+            }
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(attempts, 2);
+}
+
+#[test]
+fn retry_policy_stops_when_out_of_time() {
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        initial_delay_secs: 0.1,
+        backoff_factor: 1.0,
+        jitter: 0.0,
+        max_total_secs: Some(0.25),
+        ..Default::default()
+    };
+    let mut attempts = 0;
+    let result = policy.run("test", || {
+        attempts += 1;
+        bail!("failed")
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 3);
+}
+
+#[test]
+fn retry_policy_waits_that_overflow_are_out_of_time() {
+    let policy = RetryPolicy {
+        max_attempts: 5,
+        initial_delay_secs: 30.0,
+        backoff_factor: 1e30,
+        jitter: 0.5,
+        max_total_secs: Some(60.0),
+        ..Default::default()
+    };
+    assert_eq!(policy.delay_before_retry(3), Duration::MAX);
+    let start = Instant::now();
+    let policy = RetryPolicy {
+        initial_delay_secs: 1e300,
+        ..policy
+    };
+    let result = policy.run("test", || bail!("failed"));
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(error.contains("not enough time left"), "{error}");
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn retry_policy_waits_over_maximum_give_up_without_budget() {
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        initial_delay_secs: 1e300,
+        backoff_factor: 1e30,
+        jitter: 0.0,
+        max_total_secs: None,
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let mut attempts = 0;
+    let result = policy.run("test", || {
+        attempts += 1;
+        bail!("failed")
+    });
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(error.contains("exceeds the maximum"), "{error}");
+    assert_eq!(attempts, 1);
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn invalid_retry_policy_rejected_on_load() {
+    let config_folder = Path::new(env!("CARGO_TARGET_TMPDIR")).join("invalid_retry_policy");
+    fs::create_dir_all(&config_folder).unwrap();
+    for retry in [
+        "(max_attempts: 0)",
+        "(max_retry_after_secs: -1)",
+        "(initial_delay_secs: -1)",
+        "(backoff_factor: 0.5)",
+        "(jitter: -0.1)",
+        "(max_total_secs: Some(inf))",
+    ] {
+        fs::write(
+            config_folder.join(NotificationConfig::FILENAME),
+            format!(
+                r#"NotificationConfig(channels: [(kind: Slack((webhook_url: "http://localhost")), retry: {retry})])"#
+            ),
+        )
+        .unwrap();
+        let error = format!(
+            "{:#}",
+            NotificationConfig::load(&config_folder).unwrap_err()
+        );
+        assert!(
+            error.contains("invalid retry policy for channel #1"),
+            "{error}"
+        );
+    }
+}
+
+#[test]
+fn retry_policy_applied_to_email() {
+    // Nothing listening on the port
+    let unused_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = local_email_config(Some(unused_port), SmtpAuth::None);
+    let start = Instant::now();
+
+    let result = Email::new(&config, fast_retry_policy())
+        .unwrap()
+        .send(&Message::new(Severity::Error, "test"));
+
+    let error = format!("{:#}", result.unwrap_err());
+    assert!(error.contains("after 4 attempts"), "{error}");
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+/// Records the text of the messages it delivers and fails while `should_fail` is set
+#[derive(Clone, Default)]
+struct RecordingNotifier {
+    should_fail: Rc<Cell<bool>>,
+    delivered: CallLog,
+}
+
+impl Notifier for RecordingNotifier {
+    fn name(&self) -> &str {
+        "recording"
+    }
+
+    fn send(&self, msg: &Message) -> anyhow::Result<()> {
+        if self.should_fail.get() {
+            bail!("recording notifier set to fail")
+        }
+        self.delivered.borrow_mut().push(msg.text.clone());
+        Ok(())
+    }
+}
+
+impl RecordingNotifier {
+    fn channels(&self) -> Vec<Channel> {
+        vec![Channel {
+            mode: ChannelMode::Failover,
+            min_severity: None,
+            notifier: Box::new(self.clone()),
+        }]
+    }
+}
+
+fn outbox_file(name: &str) -> PathBuf {
+    let result = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.outbox.ron"));
+    let _ = fs::remove_file(&result);
+    result
+}
+
+#[test]
+fn outbox_keeps_undelivered_messages_in_order() {
+    let notifier = RecordingNotifier::default();
+    let channels = notifier.channels();
+    let path = outbox_file("in_order");
+    let mut outbox = Outbox::load(&path).unwrap();
+
+    notifier.should_fail.set(true);
+    let delivery = outbox.send_or_queue(Message::new(Severity::Error, "first"), &channels);
+    assert_eq!(delivery, Delivery::Queued);
+    outbox.save(&path).unwrap();
+
+    // Next run, still failing
+    let mut outbox = Outbox::load(&path).unwrap();
+    outbox.flush(&channels);
+    assert_eq!(outbox.messages().len(), 1);
+    notifier.should_fail.set(false);
+    // Not sent as an older message is still waiting
+    let delivery = outbox.send_or_queue(Message::new(Severity::Error, "second"), &channels);
+    assert_eq!(delivery, Delivery::Queued);
+    assert!(notifier.delivered.borrow().is_empty());
+    outbox.save(&path).unwrap();
+
+    // Next run, delivery works again
+    let mut outbox = Outbox::load(&path).unwrap();
+    outbox.flush(&channels);
+    let delivery = outbox.send_or_queue(Message::new(Severity::Info, "third"), &channels);
+    assert_eq!(delivery, Delivery::Sent);
+    assert!(outbox.messages().is_empty());
+    assert_eq!(*notifier.delivered.borrow(), ["first", "second", "third"]);
+}
+
+#[test]
+fn empty_channel_list_rejected_on_load() {
+    let config_folder = Path::new(env!("CARGO_TARGET_TMPDIR")).join("empty_channel_list");
+    fs::create_dir_all(&config_folder).unwrap();
+    fs::write(
+        config_folder.join(NotificationConfig::FILENAME),
+        "NotificationConfig(channels: [])",
+    )
+    .unwrap();
+    let error = format!(
+        "{:#}",
+        NotificationConfig::load(&config_folder).unwrap_err()
+    );
+    assert!(error.contains("no notification channels set"), "{error}");
+}
+
+#[test]
+fn outbox_max_age_too_large_rejected_on_load() {
+    let config_folder = Path::new(env!("CARGO_TARGET_TMPDIR")).join("outbox_max_age_too_large");
+    fs::create_dir_all(&config_folder).unwrap();
+    for hours in [u64::MAX, 3_000_000_000_000] {
+        fs::write(
+            config_folder.join(NotificationConfig::FILENAME),
+            format!(
+                r#"NotificationConfig(channels: [(kind: Slack((webhook_url: "http://localhost")))], outbox_max_age_hours: {hours})"#
+            ),
+        )
+        .unwrap();
+        let error = format!(
+            "{:#}",
+            NotificationConfig::load(&config_folder).unwrap_err()
+        );
+        assert!(
+            error.contains("outbox_max_age_hours is too large"),
+            "{error}"
+        );
+    }
+}
+
+#[test]
+fn outbox_gives_up_on_old_messages() {
+    let path = outbox_file("expired");
+    fs::write(
+        &path,
+        r#"Outbox(messages: [
+            (queued_at: "2020-01-01T00:00:00+00:00", msg: (severity: Error, text: "too old")),
+        ])"#,
+    )
+    .unwrap();
+    let mut outbox = Outbox::load(&path).unwrap();
+    outbox.push(Message::new(Severity::Error, "recent"));
+
+    let expired = outbox.remove_expired(TimeDelta::hours(48));
+
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].msg.text, "too old");
+    assert_eq!(outbox.messages().len(), 1);
+    assert_eq!(outbox.messages()[0].msg.text, "recent");
+}
+
+#[test]
+fn logs_not_marked_processed_until_delivered_or_queued() {
+    let notifier = RecordingNotifier::default();
+    let channels = notifier.channels();
+    let mut app_state = AppState::new_with_min_dates(Path::new("tests").join("sample_logs"));
+    let before = app_state.clone();
+    let mut outbox = Outbox::default();
+
+    // Neither sent nor saved to the outbox
+    notifier.should_fail.set(true);
+    let unwritable_path = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("folder_that_does_not_exist")
+        .join("state.outbox.ron");
+    let result = report_new_logs(&mut app_state, &mut outbox, &unwritable_path, &channels);
+    assert!(result.is_err());
+    assert_eq!(app_state, before, "logs should not be marked as processed");
+
+    // Next run, delivery works
+    notifier.should_fail.set(false);
+    let mut outbox = Outbox::default();
+    let delivery = report_new_logs(
+        &mut app_state,
+        &mut outbox,
+        &outbox_file("undelivered"),
+        &channels,
+    )
+    .unwrap();
+    assert_eq!(delivery, Some(Delivery::Sent));
+    assert!(notifier.delivered.borrow()[0].contains("6 logs with 10 error and warnings"));
+    assert_eq!(
+        app_state.watched_folders()[0]
+            .latest_log_datetime()
+            .to_string(),
+        "2024-11-14 08:30:15"
+    );
+}
+
+#[test]
+fn logs_marked_processed_when_sent_even_if_outbox_not_saved() {
+    let notifier = RecordingNotifier::default();
+    let channels = notifier.channels();
+    let mut app_state = AppState::new_with_min_dates(Path::new("tests").join("sample_logs"));
+    // Changed by delivering an older message earlier in the run
+    let mut outbox = Outbox::default();
+    outbox.push(Message::new(Severity::Error, "older"));
+    outbox.flush(&channels);
+    assert!(outbox.is_changed());
+
+    let unwritable_path = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("folder_that_does_not_exist")
+        .join("state.outbox.ron");
+    let result = report_new_logs(&mut app_state, &mut outbox, &unwritable_path, &channels);
+
+    assert!(result.is_err(), "failing to save the outbox is reported");
+    assert_eq!(notifier.delivered.borrow().len(), 2);
+    assert_eq!(
+        app_state.watched_folders()[0]
+            .latest_log_datetime()
+            .to_string(),
+        "2024-11-14 08:30:15"
+    );
+}
+
+#[test]
+fn logs_marked_processed_when_queued() {
+    let notifier = RecordingNotifier::default();
+    let channels = notifier.channels();
+    let mut app_state = AppState::new_with_min_dates(Path::new("tests").join("sample_logs"));
+    let path = outbox_file("queued_logs");
+    let mut outbox = Outbox::load(&path).unwrap();
+
+    notifier.should_fail.set(true);
+    let delivery = report_new_logs(&mut app_state, &mut outbox, &path, &channels).unwrap();
+
+    assert_eq!(delivery, Some(Delivery::Queued));
+    assert_eq!(Outbox::load(&path).unwrap().messages().len(), 1);
+    assert_eq!(
+        app_state.watched_folders()[0]
+            .latest_log_datetime()
+            .to_string(),
+        "2024-11-14 08:30:15"
+    );
+}